@@ -0,0 +1,632 @@
+use crate::files::{
+    hash_bytes, hash_file, is_binary, project_root, BINARY_SNIFF_LEN, PROMPT_IGNORE_FILENAME,
+};
+use crate::{remote, AppError, FileEntry, Source, State};
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The filters and settings of an `add`, matching the flags of `pb add`. They are
+/// stored with every added path, so `refresh` walks it the same way again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AddOptions {
+    /// Globs of files to skip, in addition to the state's default excludes
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Skip files with these extensions, with or without a leading `.`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude_ext: Vec<String>,
+    /// Only add files with these extensions, unless it's empty
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub only_ext: Vec<String>,
+    /// Don't skip `*.lock` files
+    #[serde(skip_serializing_if = "is_false")]
+    pub include_lock: bool,
+    /// Don't respect `.promptignore`, `.gitignore`, `.ignore` and git exclude files
+    #[serde(skip_serializing_if = "is_false")]
+    pub no_ignore: bool,
+    /// Don't apply ignore files from the parent directories of the added paths
+    #[serde(skip_serializing_if = "is_false")]
+    pub no_parent_ignore: bool,
+    /// Include hidden files and directories
+    #[serde(skip_serializing_if = "is_false")]
+    pub hidden: bool,
+    /// Add files that look binary instead of skipping them
+    #[serde(skip_serializing_if = "is_false")]
+    pub allow_binary: bool,
+    /// Skip files larger than this many bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+    /// Descend into symlinked directories
+    #[serde(skip_serializing_if = "is_false")]
+    pub follow_symlinks: bool,
+    /// Only descend this many directory levels (1 = the directory's own files)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    /// Store paths relative to this directory instead of the project root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_to: Option<PathBuf>,
+    /// Only add files last modified at or after this time
+    #[serde(skip_serializing_if = "Option::is_none", with = "rfc3339")]
+    pub modified_after: Option<SystemTime>,
+    /// Only add files last modified before this time
+    #[serde(skip_serializing_if = "Option::is_none", with = "rfc3339")]
+    pub modified_before: Option<SystemTime>,
+    /// Skip files outside the project root instead of warning about them
+    #[serde(skip_serializing_if = "is_false")]
+    pub no_external: bool,
+    /// Label the added files with these tags
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Update the stored hash, size, path and tags of files that are already in the state
+    #[serde(skip)]
+    pub force: bool,
+    /// Fail instead of warning when a path or glob doesn't match any files
+    #[serde(skip)]
+    pub strict: bool,
+}
+
+impl AddOptions {
+    /// Whether these are the default options, which aren't stored.
+    pub fn is_default(&self) -> bool {
+        *self == AddOptions::default()
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Stores the `--modified-after` and `--modified-before` times in RFC 3339 format.
+mod rfc3339 {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::SystemTime;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.collect_str(&humantime::format_rfc3339_seconds(*time)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|time| humantime::parse_rfc3339(&time).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// What happened to the files found while walking the paths given to `add`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AddSummary {
+    pub added: usize,
+    /// Files in the state whose entry was updated because of --force
+    pub updated: usize,
+    /// Files that were found but are in the state already
+    pub already_present: usize,
+    pub binary: usize,
+    pub duplicate: usize,
+    pub too_large: usize,
+    /// Files that were found but couldn't be accessed
+    pub unreadable: usize,
+    /// Files outside the project root, skipped because of --no-external
+    pub external: usize,
+}
+
+impl AddSummary {
+    pub fn merge(&mut self, other: AddSummary) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.already_present += other.already_present;
+        self.binary += other.binary;
+        self.duplicate += other.duplicate;
+        self.too_large += other.too_large;
+        self.unreadable += other.unreadable;
+        self.external += other.external;
+    }
+
+    /// The number of files that were found but not added.
+    pub fn skipped(&self) -> usize {
+        self.binary + self.duplicate + self.too_large + self.unreadable + self.external
+    }
+}
+
+/// The summary as lines for people, e.g. `3 file(s) added successfully.`
+impl fmt::Display for AddSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.added > 0 {
+            writeln!(f, "{} file(s) added successfully.", self.added)?;
+        } else {
+            writeln!(f, "No new files added.")?;
+        }
+        if self.updated > 0 {
+            writeln!(f, "{} file(s) already in the state updated.", self.updated)?;
+        }
+        if self.binary > 0 {
+            writeln!(
+                f,
+                "Skipped {} binary file(s). Use --allow-binary to add them.",
+                self.binary
+            )?;
+        }
+        if self.duplicate > 0 {
+            writeln!(f, "Skipped {} duplicate-content file(s).", self.duplicate)?;
+        }
+        if self.too_large > 0 {
+            writeln!(
+                f,
+                "Skipped {} file(s) exceeding the maximum size.",
+                self.too_large
+            )?;
+        }
+        if self.unreadable > 0 {
+            writeln!(
+                f,
+                "Skipped {} file(s) that couldn't be read.",
+                self.unreadable
+            )?;
+        }
+        if self.external > 0 {
+            writeln!(
+                f,
+                "Skipped {} file(s) outside the project root.",
+                self.external
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks `patterns` and appends every new file that passes the filters in `options`
+/// to the state. The state isn't saved. Each pattern is a file, a directory that
+/// is walked recursively, or a glob, and the same filters apply to all of them.
+pub fn add_files(
+    state: &mut State,
+    patterns: &[String],
+    options: &AddOptions,
+) -> Result<AddSummary, AppError> {
+    // Excludes are matched relative to the current directory, whichever argument
+    // a file was found through. Rooting them at its absolute path also covers the
+    // absolute paths `refresh` walks.
+    let mut override_builder = ignore::overrides::OverrideBuilder::new(std::env::current_dir()?);
+    // Git internals are hidden, but `--hidden` shouldn't pull them in either.
+    override_builder.add("!.git/")?;
+    if !options.include_lock {
+        override_builder.add("!*.lock")?;
+    }
+    for exclude in state.default_excludes.iter().chain(&options.exclude) {
+        override_builder.add(&format!("!{}", exclude))?;
+    }
+    let overrides = override_builder.build()?;
+
+    let root = project_root(&std::env::current_dir()?);
+    let mut summary = AddSummary::default();
+
+    // Paths are canonicalized, which resolves symlinks: a file reached through a
+    // symlink and through its target (or through two roots) is only added once.
+    let mut existing_paths: std::collections::HashSet<_> = state
+        .files
+        .iter()
+        .filter_map(|f| f.local_path().map(Path::to_path_buf))
+        .collect();
+    let in_state = existing_paths.clone();
+    let mut relative_paths: std::collections::HashSet<_> = state
+        .files
+        .iter()
+        .map(|f| f.relative_path.clone())
+        .collect();
+    let relative_to = match &options.relative_to {
+        Some(base) => Some(fs::canonicalize(base).map_err(|e| {
+            AppError::CustomError(format!("Invalid --relative-to {}: {}", base.display(), e))
+        })?),
+        None => None,
+    };
+    let mut existing_hashes: std::collections::HashSet<_> =
+        state.files.iter().filter_map(|f| f.hash.clone()).collect();
+    // With --force, files in the state are updated once each.
+    let mut refreshed = std::collections::HashSet::new();
+    for pattern in patterns {
+        let (walk_root, glob) = if is_glob(pattern) && !Path::new(pattern).exists() {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()?
+                .compile_matcher();
+            (glob_base(pattern), Some(glob))
+        } else {
+            (PathBuf::from(pattern), None)
+        };
+        // Counts the files a pattern matched, whether or not they end up added.
+        let mut matched = 0;
+        // A path that doesn't exist matches nothing, which is reported below.
+        let walk = walk_root
+            .exists()
+            .then(|| walker(&walk_root, options, overrides.clone()).build());
+        for result in walk.into_iter().flatten() {
+            // A broken symlink, a symlink loop or an unreadable directory is
+            // skipped like an unreadable file, instead of aborting the whole add.
+            let entry = match result {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("Warning: {}. Skipping it.", e);
+                    summary.unreadable += 1;
+                    continue;
+                }
+            };
+            let file_path = entry.path();
+            if !file_path.is_file() {
+                continue;
+            }
+            // Like excludes, extension filters don't apply to files named explicitly.
+            if entry.depth() > 0 && !extension_allowed(file_path, options) {
+                continue;
+            }
+            if let Some(glob) = &glob {
+                let stripped = file_path.strip_prefix(".").unwrap_or(file_path);
+                if !glob.is_match(file_path) && !glob.is_match(stripped) {
+                    continue;
+                }
+            }
+            matched += 1;
+            if options.modified_after.is_some() || options.modified_before.is_some() {
+                let modified = match fs::metadata(file_path).and_then(|m| m.modified()) {
+                    Ok(modified) => modified,
+                    Err(e) => {
+                        skip_unreadable(&mut summary, file_path, &e.into());
+                        continue;
+                    }
+                };
+                if options.modified_after.is_some_and(|after| modified < after)
+                    || options
+                        .modified_before
+                        .is_some_and(|before| modified >= before)
+                {
+                    continue;
+                }
+            }
+            // A file that vanished or can't be accessed is skipped, so that one bad
+            // file doesn't abort adding all the others.
+            let (absolute_path, size) = match fs::canonicalize(file_path)
+                .and_then(|path| Ok((path, entry.metadata().map_err(std::io::Error::other)?.len())))
+            {
+                Ok(found) => found,
+                Err(e) => {
+                    skip_unreadable(&mut summary, file_path, &e.into());
+                    continue;
+                }
+            };
+            if options.force && in_state.contains(&absolute_path) {
+                if refreshed.insert(absolute_path.clone()) {
+                    let hash = match hash_file(file_path) {
+                        Ok(hash) => hash,
+                        Err(e) => {
+                            skip_unreadable(&mut summary, file_path, &e);
+                            continue;
+                        }
+                    };
+                    let file = state
+                        .files
+                        .iter_mut()
+                        .find(|f| f.local_path() == Some(&absolute_path))
+                        .expect("files in the state have an entry");
+                    file.hash = Some(hash);
+                    file.size = Some(size);
+                    file.relative_to = relative_to.clone();
+                    let relative_path = stored_relative_path(
+                        &absolute_path,
+                        file_path,
+                        relative_to.as_deref(),
+                        root.as_deref(),
+                    );
+                    if relative_path != file.relative_path {
+                        relative_paths.remove(&file.relative_path);
+                        file.relative_path = unique_relative_path(
+                            &mut relative_paths,
+                            relative_path,
+                            &absolute_path,
+                        );
+                    }
+                    for tag in &options.tags {
+                        if !file.tags.contains(tag) {
+                            file.tags.push(tag.clone());
+                        }
+                    }
+                    summary.updated += 1;
+                }
+                continue;
+            }
+            if existing_paths.insert(absolute_path.clone()) {
+                // Most likely grabbed from a sibling repository by accident.
+                let external = root
+                    .as_deref()
+                    .filter(|root| !absolute_path.starts_with(root));
+                if let Some(root) = external {
+                    if options.no_external {
+                        eprintln!(
+                            "Skipping {} (outside the project root {})",
+                            file_path.display(),
+                            root.display()
+                        );
+                        summary.external += 1;
+                        continue;
+                    }
+                }
+                if let Some(max_size) = options.max_size {
+                    if size > max_size {
+                        eprintln!(
+                            "Skipping {} ({} bytes, limit is {} bytes)",
+                            file_path.display(),
+                            size,
+                            max_size
+                        );
+                        summary.too_large += 1;
+                        continue;
+                    }
+                }
+                let binary = match is_binary(file_path) {
+                    Ok(binary) => binary,
+                    Err(e) => {
+                        skip_unreadable(&mut summary, file_path, &e);
+                        continue;
+                    }
+                };
+                if !options.allow_binary && binary {
+                    summary.binary += 1;
+                    continue;
+                }
+                // The path check above is cheap, hashing catches the same contents
+                // reached through another path, e.g. a copy or a hard link. Empty
+                // files (like `__init__.py`) are distinct even though they hash alike.
+                let hash = match hash_file(file_path) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        skip_unreadable(&mut summary, file_path, &e);
+                        continue;
+                    }
+                };
+                if size > 0 && !existing_hashes.insert(hash.clone()) {
+                    summary.duplicate += 1;
+                    continue;
+                }
+                let relative_path = stored_relative_path(
+                    &absolute_path,
+                    file_path,
+                    relative_to.as_deref(),
+                    root.as_deref(),
+                );
+                let relative_path =
+                    unique_relative_path(&mut relative_paths, relative_path, &absolute_path);
+                if let Some(root) = external {
+                    eprintln!(
+                        "Warning: {} is outside the project root {}, storing it as {}",
+                        file_path.display(),
+                        root.display(),
+                        relative_path
+                    );
+                }
+                let entry = FileEntry {
+                    relative_path,
+                    source: Source::Local { absolute_path },
+                    hash: Some(hash),
+                    size: Some(size),
+                    relative_to: relative_to.clone(),
+                    range: None,
+                    tags: options.tags.clone(),
+                    display_name: None,
+                    added_at: Some(now_rfc3339()),
+                    order: 0,
+                };
+                state.push_file(entry);
+                summary.added += 1;
+            } else if in_state.contains(&absolute_path) {
+                summary.already_present += 1;
+            }
+        }
+        // Most likely a typo, which would otherwise look like everything was added already.
+        if matched == 0 {
+            let message = format!("'{}' didn't match any files", pattern);
+            if options.strict {
+                return Err(AppError::CustomError(message));
+            }
+            eprintln!("Warning: {}", message);
+        }
+    }
+    Ok(summary)
+}
+
+/// Whether `path` passes `--only-ext` and `--exclude-ext`. Extensions are compared
+/// case-insensitively, with or without a leading `.`.
+fn extension_allowed(path: &Path, options: &AddOptions) -> bool {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy())
+        .unwrap_or_default();
+    let matches = |extensions: &[String]| {
+        extensions
+            .iter()
+            .any(|ext| ext.trim_start_matches('.').eq_ignore_ascii_case(&extension))
+    };
+    (options.only_ext.is_empty() || matches(&options.only_ext)) && !matches(&options.exclude_ext)
+}
+
+fn skip_unreadable(summary: &mut AddSummary, path: &Path, error: &AppError) {
+    eprintln!("Warning: Skipping {}: {}", path.display(), error);
+    summary.unreadable += 1;
+}
+
+/// The path a file is stored under: relative to `relative_to` if it's given,
+/// otherwise relative to the project root, otherwise as it was found.
+fn stored_relative_path(
+    absolute_path: &Path,
+    file_path: &Path,
+    relative_to: Option<&Path>,
+    root: Option<&Path>,
+) -> String {
+    match relative_to {
+        Some(base) => {
+            path_relative_to(absolute_path, base).unwrap_or_else(|| absolute_path.to_path_buf())
+        }
+        None => root
+            .and_then(|root| absolute_path.strip_prefix(root).ok())
+            .unwrap_or(file_path)
+            .to_path_buf(),
+    }
+    .to_string_lossy()
+    .into()
+}
+
+/// Claims `relative_path` in `taken`. `print` identifies files by their relative
+/// path, so two files must never share one: if it's taken, the file is stored
+/// under its absolute path, which is unique.
+fn unique_relative_path(
+    taken: &mut std::collections::HashSet<String>,
+    relative_path: String,
+    absolute_path: &Path,
+) -> String {
+    if taken.insert(relative_path.clone()) {
+        return relative_path;
+    }
+    let unique = absolute_path.to_string_lossy().into_owned();
+    eprintln!(
+        "Warning: Another file in the state is already stored as {}, storing {} with its absolute path instead.",
+        relative_path, unique
+    );
+    taken.insert(unique.clone());
+    unique
+}
+
+/// Creates a walker for one `add` argument, configured by `options`.
+fn walker(
+    root: &Path,
+    options: &AddOptions,
+    overrides: ignore::overrides::Override,
+) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    // Walk in a stable order so the same add produces the same state on every
+    // machine. New files are appended, so existing entries keep their position.
+    builder.sort_by_file_name(|a, b| a.cmp(b));
+    builder.hidden(!options.hidden);
+    builder.follow_links(options.follow_symlinks);
+    builder.max_depth(options.max_depth);
+    builder.parents(!options.no_parent_ignore);
+    if !options.no_ignore {
+        builder.add_custom_ignore_filename(PROMPT_IGNORE_FILENAME);
+    }
+    if options.no_ignore {
+        builder
+            .git_ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .ignore(false);
+    }
+    builder.overrides(overrides);
+    builder
+}
+
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// The directory a glob is walked from: its leading components without wildcards,
+/// e.g. `src/bin` for `src/bin/**/*.rs`.
+fn glob_base(pattern: &str) -> PathBuf {
+    let base: PathBuf = Path::new(pattern)
+        .components()
+        .take_while(|component| !is_glob(&component.as_os_str().to_string_lossy()))
+        .collect();
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    }
+}
+
+/// Returns `path` relative to `base`, going up with `..` where needed, or `None` if
+/// there's no relative path between them (e.g. on different drives). Both paths
+/// must be canonical.
+fn path_relative_to(path: &Path, base: &Path) -> Option<PathBuf> {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return None;
+    }
+    while path_components.peek().is_some() && path_components.peek() == base_components.peek() {
+        path_components.next();
+        base_components.next();
+    }
+    let mut relative: PathBuf = base_components.map(|_| "..").collect();
+    relative.extend(path_components);
+    Some(relative)
+}
+
+/// The current time in RFC 3339 format, e.g. `2024-05-01T12:30:00Z`.
+pub fn now_rfc3339() -> String {
+    humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string()
+}
+
+/// Downloads each URL once, to fail early if it can't be fetched and to apply the
+/// same checks as for local files, and adds it as a remote file.
+pub fn add_remote_files(
+    state: &mut State,
+    urls: &[String],
+    options: &AddOptions,
+) -> Result<AddSummary, AppError> {
+    let mut summary = AddSummary::default();
+    for url in urls {
+        let source = Source::Remote { url: url.clone() };
+        if state.files.iter().any(|file| file.source == source) {
+            summary.already_present += 1;
+            continue;
+        }
+        let contents = remote::fetch(url)?;
+        if let Some(max_size) = options.max_size.filter(|&max| contents.len() as u64 > max) {
+            eprintln!(
+                "Skipping {} ({} bytes, limit is {} bytes)",
+                url,
+                contents.len(),
+                max_size
+            );
+            summary.too_large += 1;
+            continue;
+        }
+        let sniffed = &contents[..contents.len().min(BINARY_SNIFF_LEN as usize)];
+        if !options.allow_binary && sniffed.contains(&0) {
+            summary.binary += 1;
+            continue;
+        }
+        let hash = hash_bytes(&contents);
+        if !contents.is_empty()
+            && state
+                .files
+                .iter()
+                .any(|file| file.hash.as_ref() == Some(&hash))
+        {
+            summary.duplicate += 1;
+            continue;
+        }
+        // The URL doubles as the path, so it's what `print` shows in the `path` attribute.
+        state.push_file(FileEntry {
+            relative_path: url.clone(),
+            source,
+            hash: Some(hash),
+            size: Some(contents.len() as u64),
+            relative_to: None,
+            range: None,
+            tags: options.tags.clone(),
+            display_name: None,
+            added_at: Some(now_rfc3339()),
+            order: 0,
+        });
+        summary.added += 1;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,449 @@
+/// A kind of string literal, which comment markers inside of don't count.
+struct Quote {
+    delimiter: &'static str,
+    /// Whether a backslash escapes the next character
+    escapes: bool,
+    /// Whether the literal may span lines
+    multiline: bool,
+}
+
+const fn quote(delimiter: &'static str, escapes: bool, multiline: bool) -> Quote {
+    Quote {
+        delimiter,
+        escapes,
+        multiline,
+    }
+}
+
+/// The comment and string syntax of a language, as far as stripping comments needs it.
+struct Syntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    /// Whether block comments nest, like in Rust
+    nested: bool,
+    /// Longer delimiters first, e.g. `"""` before `"`
+    quotes: &'static [Quote],
+    /// Char literals, lifetimes and raw strings
+    rust: bool,
+}
+
+const C_BLOCK: Option<(&str, &str)> = Some(("/*", "*/"));
+const C_QUOTES: &[Quote] = &[quote("\"", true, false), quote("'", true, false)];
+const JS_QUOTES: &[Quote] = &[
+    quote("`", true, true),
+    quote("\"", true, false),
+    quote("'", true, false),
+];
+const TRIPLE_QUOTES: &[Quote] = &[
+    quote("\"\"\"", true, true),
+    quote("\"", true, false),
+    quote("'", true, false),
+];
+const GO_QUOTES: &[Quote] = &[
+    quote("`", false, true),
+    quote("\"", true, false),
+    quote("'", true, false),
+];
+/// Also used for Dart, which has the same string literals.
+const PYTHON_QUOTES: &[Quote] = &[
+    quote("\"\"\"", true, true),
+    quote("'''", true, true),
+    quote("\"", true, false),
+    quote("'", true, false),
+];
+const TOML_QUOTES: &[Quote] = &[
+    quote("\"\"\"", true, true),
+    quote("'''", false, true),
+    quote("\"", true, false),
+    quote("'", false, false),
+];
+const SQL_QUOTES: &[Quote] = &[quote("'", false, true), quote("\"", false, true)];
+/// Char literals and raw strings are handled separately.
+const RUST_QUOTES: &[Quote] = &[quote("\"", true, true)];
+
+/// Returns the syntax for a language identifier from the language table. Languages
+/// whose comments can't be told apart from code without a real parser, like shell
+/// scripts with heredocs or YAML block scalars, aren't supported.
+fn syntax(language: &str) -> Option<Syntax> {
+    let c_style = |quotes, nested| Syntax {
+        line: &["//"],
+        block: C_BLOCK,
+        nested,
+        quotes,
+        rust: false,
+    };
+    Some(match language {
+        "c" | "cpp" | "csharp" | "php" => c_style(C_QUOTES, false),
+        "javascript" | "jsx" | "typescript" | "tsx" => c_style(JS_QUOTES, false),
+        "java" => c_style(TRIPLE_QUOTES, false),
+        "kotlin" | "scala" | "swift" => c_style(TRIPLE_QUOTES, true),
+        "go" => c_style(GO_QUOTES, false),
+        "dart" => c_style(PYTHON_QUOTES, false),
+        "rust" => Syntax {
+            line: &["//"],
+            block: C_BLOCK,
+            nested: true,
+            quotes: RUST_QUOTES,
+            rust: true,
+        },
+        // `//` isn't a comment in CSS, and SCSS files often contain unquoted URLs.
+        "css" | "scss" => Syntax {
+            line: &[],
+            block: C_BLOCK,
+            nested: false,
+            quotes: C_QUOTES,
+            rust: false,
+        },
+        "python" => Syntax {
+            line: &["#"],
+            block: None,
+            nested: false,
+            quotes: PYTHON_QUOTES,
+            rust: false,
+        },
+        "toml" => Syntax {
+            line: &["#"],
+            block: None,
+            nested: false,
+            quotes: TOML_QUOTES,
+            rust: false,
+        },
+        "sql" => Syntax {
+            line: &["--"],
+            block: C_BLOCK,
+            nested: false,
+            quotes: SQL_QUOTES,
+            rust: false,
+        },
+        "html" | "xml" => Syntax {
+            line: &[],
+            block: Some(("<!--", "-->")),
+            nested: false,
+            quotes: &[],
+            rust: false,
+        },
+        _ => return None,
+    })
+}
+
+/// Removes the line and block comments from `source`, a file in `language` (an
+/// identifier from the language table). Lines that only contained comments are
+/// removed, or left empty if `keep_lines` is set, so line numbers still match the
+/// file. Returns `None` for languages that aren't supported, and for files that
+/// can't be parsed with confidence, e.g. because of an unterminated string, which
+/// should be passed through unchanged.
+pub fn strip(source: &str, language: &str, keep_lines: bool) -> Option<String> {
+    let syntax = syntax(language)?;
+    let mut out = Output {
+        text: String::with_capacity(source.len()),
+        line_start: 0,
+        removed: false,
+        keep_lines,
+    };
+    let mut i = 0;
+    while let Some(c) = source[i..].chars().next() {
+        let rest = &source[i..];
+        if c == '\n' {
+            out.end_line();
+            i += 1;
+        } else if syntax.line.iter().any(|marker| rest.starts_with(marker))
+            // A shebang is kept, it says how the script is run.
+            && !(i == 0 && rest.starts_with("#!"))
+        {
+            i += rest.find('\n').unwrap_or(rest.len());
+            out.removed = true;
+        } else if let Some((start, end)) = syntax.block.filter(|(start, _)| rest.starts_with(start))
+        {
+            let length = block_comment_len(rest, start, end, syntax.nested)?;
+            for _ in rest[..length].matches('\n') {
+                out.removed = true;
+                out.end_line();
+            }
+            out.removed = true;
+            i += length;
+            // `a/* b */c` is `a c`, not `ac`.
+            let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            if is_word(out.text.chars().next_back()) && is_word(source[i..].chars().next()) {
+                out.text.push(' ');
+            }
+        } else if let Some(length) = literal_len(source, i, &syntax) {
+            out.copy(&rest[..length]);
+            i += length;
+        } else if syntax
+            .quotes
+            .iter()
+            .any(|quote| rest.starts_with(quote.delimiter))
+        {
+            // An unterminated string, most likely something this parser doesn't know.
+            return None;
+        } else {
+            out.text.push(c);
+            i += c.len_utf8();
+        }
+    }
+    out.end_file();
+    Some(out.text)
+}
+
+/// The stripped source, built line by line.
+struct Output {
+    text: String,
+    /// Where the current line starts in `text`
+    line_start: usize,
+    /// Whether a comment was removed from the current line
+    removed: bool,
+    keep_lines: bool,
+}
+
+impl Output {
+    /// Copies a string literal, which may contain newlines.
+    fn copy(&mut self, literal: &str) {
+        self.text.push_str(literal);
+        if let Some(newline) = literal.rfind('\n') {
+            self.line_start = self.text.len() - (literal.len() - newline - 1);
+            self.removed = false;
+        }
+    }
+
+    fn end_line(&mut self) {
+        if self.trim_line() {
+            self.text.push('\n');
+        }
+        self.line_start = self.text.len();
+        self.removed = false;
+    }
+
+    fn end_file(&mut self) {
+        self.trim_line();
+    }
+
+    /// Removes the whitespace left before a removed comment, and the whole line if
+    /// nothing else is left. Returns whether the line's newline should be kept.
+    fn trim_line(&mut self) -> bool {
+        if !self.removed {
+            return true;
+        }
+        let line = &self.text[self.line_start..];
+        let crlf = line.ends_with('\r');
+        let kept = line.trim_end().len();
+        if kept == 0 && !self.keep_lines {
+            self.text.truncate(self.line_start);
+            return false;
+        }
+        self.text.truncate(self.line_start + kept);
+        if crlf {
+            self.text.push('\r');
+        }
+        true
+    }
+}
+
+/// The length of the block comment at the start of `s`, or `None` if it isn't closed.
+fn block_comment_len(s: &str, start: &str, end: &str, nested: bool) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < s.len() {
+        if s[i..].starts_with(start) && (nested || depth == 0) {
+            depth += 1;
+            i += start.len();
+        } else if s[i..].starts_with(end) {
+            depth -= 1;
+            i += end.len();
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += s[i..].chars().next()?.len_utf8();
+        }
+    }
+    None
+}
+
+/// The length of the string or char literal starting at `source[i..]`, if there is
+/// one and it's terminated.
+fn literal_len(source: &str, i: usize, syntax: &Syntax) -> Option<usize> {
+    let rest = &source[i..];
+    if syntax.rust {
+        if let Some(length) = rust_literal_len(source, i) {
+            return Some(length);
+        }
+    }
+    let quote = syntax
+        .quotes
+        .iter()
+        .find(|quote| rest.starts_with(quote.delimiter))?;
+    let mut j = quote.delimiter.len();
+    while j < rest.len() {
+        let c = rest[j..].chars().next()?;
+        if rest[j..].starts_with(quote.delimiter) {
+            return Some(j + quote.delimiter.len());
+        } else if c == '\\' && quote.escapes {
+            j += 1;
+            j += rest[j..].chars().next().map_or(0, char::len_utf8);
+        } else if c == '\n' && !quote.multiline {
+            return None;
+        } else {
+            j += c.len_utf8();
+        }
+    }
+    None
+}
+
+/// The length of a Rust char literal (`'"'`, `'\''`) or raw string (`r#"..."#`)
+/// starting at `source[i..]`. A `'` that starts a lifetime isn't a literal.
+fn rust_literal_len(source: &str, i: usize) -> Option<usize> {
+    let rest = &source[i..];
+    let mut chars = rest.chars();
+    match chars.next()? {
+        '\'' => match chars.next()? {
+            '\\' => rest.get(3..)?.find('\'').map(|end| end + 4),
+            c if chars.next() == Some('\'') => Some(c.len_utf8() + 2),
+            _ => Some(1),
+        },
+        'r' => {
+            // The `r` of `br"..."` follows a `b`, other identifiers can't end in one.
+            let before = source[..i].strip_suffix('b').unwrap_or(&source[..i]);
+            if before
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            {
+                return None;
+            }
+            let hashes = rest[1..].len() - rest[1..].trim_start_matches('#').len();
+            if !rest[1 + hashes..].starts_with('"') {
+                return None;
+            }
+            let closing = format!("\"{}", "#".repeat(hashes));
+            let body = 2 + hashes;
+            rest[body..]
+                .find(&closing)
+                .map(|end| body + end + closing.len())
+        }
+        _ => None,
+    }
+}
+
+/// Formats `text` as a one-line comment in `language`, e.g. `// src/main.rs` for
+/// Rust. Languages without comments, or that aren't known, get a `#` comment.
+pub fn comment_line(language: Option<&str>, text: &str) -> String {
+    let (start, end) = match language {
+        Some(
+            "bash" | "dockerfile" | "elixir" | "makefile" | "perl" | "python" | "ruby" | "toml"
+            | "yaml",
+        ) => ("#", ""),
+        Some("haskell" | "lua" | "sql") => ("--", ""),
+        Some("markdown") => ("<!--", "-->"),
+        Some(language) => match syntax(language) {
+            Some(Syntax {
+                line: [line, ..], ..
+            }) => (*line, ""),
+            Some(Syntax {
+                block: Some((start, end)),
+                ..
+            }) => (start, end),
+            _ => ("#", ""),
+        },
+        None => ("#", ""),
+    };
+    if end.is_empty() {
+        format!("{} {}", start, text)
+    } else {
+        format!("{} {} {}", start, text, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripped(source: &str, language: &str) -> String {
+        strip(source, language, false).expect("source can be parsed")
+    }
+
+    #[test]
+    fn rust_comments_are_removed() {
+        let source = "// header\nfn main() { // entry\n    run(); /* block */\n}\n";
+        assert_eq!(stripped(source, "rust"), "fn main() {\n    run();\n}\n");
+    }
+
+    #[test]
+    fn rust_strings_keep_comment_markers() {
+        let source = "let url = \"https://example.com\"; // link\nlet s = \"/* not */\";\n";
+        assert_eq!(
+            stripped(source, "rust"),
+            "let url = \"https://example.com\";\nlet s = \"/* not */\";\n"
+        );
+        let source = "let raw = r#\"a \"// b\"#;\nlet c = '\"'; // quote\n";
+        assert_eq!(
+            stripped(source, "rust"),
+            "let raw = r#\"a \"// b\"#;\nlet c = '\"';\n"
+        );
+    }
+
+    #[test]
+    fn rust_block_comments_nest_and_lifetimes_are_not_chars() {
+        let source = "/* outer /* inner */ still */fn f<'a>(x: &'a str) {}\n";
+        assert_eq!(stripped(source, "rust"), "fn f<'a>(x: &'a str) {}\n");
+    }
+
+    #[test]
+    fn python_comments_are_removed() {
+        let source = "#!/usr/bin/env python\n# setup\nx = 1  # the answer\n";
+        assert_eq!(stripped(source, "python"), "#!/usr/bin/env python\nx = 1\n");
+    }
+
+    #[test]
+    fn python_strings_keep_comment_markers() {
+        let source =
+            "color = \"#fff\"  # white\nurl = 'a//b'\ndoc = \"\"\"\n# not a comment\n\"\"\"\n";
+        assert_eq!(
+            stripped(source, "python"),
+            "color = \"#fff\"\nurl = 'a//b'\ndoc = \"\"\"\n# not a comment\n\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn c_comments_are_removed() {
+        let source =
+            "/*\n * License\n */\n#include <stdio.h>\nint x = 1; // one\nint a/* gap */b;\n";
+        assert_eq!(
+            stripped(source, "c"),
+            "#include <stdio.h>\nint x = 1;\nint a b;\n"
+        );
+    }
+
+    #[test]
+    fn c_strings_keep_comment_markers() {
+        let source =
+            "puts(\"// not a comment\"); // comment\nchar c = '/'; /* c */\nputs(\"\\\"/*\");\n";
+        assert_eq!(
+            stripped(source, "c"),
+            "puts(\"// not a comment\");\nchar c = '/';\nputs(\"\\\"/*\");\n"
+        );
+    }
+
+    #[test]
+    fn keep_lines_leaves_comment_lines_empty() {
+        let source = "// one\nint x; /* two\nthree */\n";
+        assert_eq!(strip(source, "c", true).unwrap(), "\nint x;\n\n");
+    }
+
+    #[test]
+    fn unsupported_or_unparsable_files_are_not_stripped() {
+        assert_eq!(strip("# comment\necho hi\n", "bash", false), None);
+        assert_eq!(strip("let s = \"unterminated // x\n", "rust", false), None);
+        assert_eq!(strip("/* unterminated\n", "c", false), None);
+    }
+
+    #[test]
+    fn comment_line_uses_the_languages_comment_syntax() {
+        assert_eq!(comment_line(Some("rust"), "src/main.rs"), "// src/main.rs");
+        assert_eq!(comment_line(Some("python"), "app.py"), "# app.py");
+        assert_eq!(
+            comment_line(Some("html"), "index.html"),
+            "<!-- index.html -->"
+        );
+        assert_eq!(comment_line(None, "LICENSE"), "# LICENSE");
+    }
+}
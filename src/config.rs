@@ -0,0 +1,49 @@
+use crate::AppError;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Default values for command line flags, read from `config.toml` in the config
+/// directory. Flags given on the command line take precedence.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub add: AddConfig,
+    pub print: PrintConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AddConfig {
+    pub exclude: Option<Vec<String>>,
+    /// A size like `100k`, as accepted by `--max-size`
+    pub max_size: Option<String>,
+    pub hidden: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrintConfig {
+    /// A format name, as accepted by `--format`
+    pub format: Option<String>,
+    pub line_numbers: Option<bool>,
+}
+
+impl Config {
+    /// Reads the config file at `path`. A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(source) => {
+                return Err(AppError::FileReadError {
+                    path: path.display().to_string(),
+                    source,
+                })
+            }
+        };
+        toml::from_str(&contents).map_err(|e| {
+            AppError::CustomError(format!("Invalid config file {}: {}", path.display(), e))
+        })
+    }
+}
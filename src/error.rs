@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error("Failed to read file: {0}")]
+    ReadError(#[from] std::io::Error),
+    #[error("Failed to parse state file: {0}")]
+    ParseError(#[from] serde_json::Error),
+    #[error("{0}")]
+    CustomError(String),
+    #[error("Failed file walker: {0}")]
+    GitIgnoreError(#[from] ignore::Error),
+    #[error("Invalid glob pattern: {0}")]
+    GlobError(#[from] globset::Error),
+    #[error("Invalid regex: {0}")]
+    RegexError(#[from] regex::Error),
+    #[error("Failed to read {path}: {source}")]
+    FileReadError {
+        path: String,
+        source: std::io::Error,
+    },
+    #[cfg(feature = "remote")]
+    #[error("Failed to fetch {url}: {source}")]
+    FetchError { url: String, source: ureq::Error },
+    #[error("Failed to fetch {url}: the server responded with HTTP status {status}")]
+    HttpStatusError { url: String, status: u16 },
+}
@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+
+/// Escapes `&`, `<` and `>` so `s` can be used as XML character data.
+pub fn xml_text(s: &str) -> Cow<'_, str> {
+    escape(s, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        _ => None,
+    })
+}
+
+/// Escapes `&`, `<` and `"` so `s` can be used inside a double-quoted XML attribute.
+pub fn xml_attr(s: &str) -> Cow<'_, str> {
+    escape(s, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '"' => Some("&quot;"),
+        _ => None,
+    })
+}
+
+/// Escapes `&`, `<`, `>`, `"` and `'` so `s` can be used as HTML text or inside a
+/// quoted HTML attribute.
+pub fn html(s: &str) -> Cow<'_, str> {
+    escape(s, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&#39;"),
+        _ => None,
+    })
+}
+
+fn escape(s: &str, replacement: impl Fn(char) -> Option<&'static str>) -> Cow<'_, str> {
+    if !s.chars().any(|c| replacement(c).is_some()) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + s.len() / 8);
+    for c in s.chars() {
+        match replacement(c) {
+            Some(entity) => escaped.push_str(entity),
+            None => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xml_text_escapes_each_metacharacter() {
+        assert_eq!(xml_text("a & b"), "a &amp; b");
+        assert_eq!(xml_text("</file>"), "&lt;/file&gt;");
+        assert_eq!(xml_text("say \"hi\" 'there'"), "say \"hi\" 'there'");
+    }
+
+    #[test]
+    fn xml_attr_escapes_each_metacharacter() {
+        assert_eq!(xml_attr("a&b"), "a&amp;b");
+        assert_eq!(xml_attr("a<b"), "a&lt;b");
+        assert_eq!(xml_attr("say \"hi\".txt"), "say &quot;hi&quot;.txt");
+        assert_eq!(xml_attr("a>b"), "a>b");
+    }
+
+    #[test]
+    fn html_escapes_each_metacharacter() {
+        assert_eq!(
+            html("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn entities_are_escaped_again() {
+        assert_eq!(xml_text("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn plain_text_is_borrowed() {
+        assert!(matches!(xml_text("fn main() {}"), Cow::Borrowed(_)));
+        assert!(matches!(xml_attr("src/main.rs"), Cow::Borrowed(_)));
+        assert!(matches!(html("häuser"), Cow::Borrowed(_)));
+        assert!(matches!(xml_text("a < b"), Cow::Owned(_)));
+    }
+}
@@ -0,0 +1,41 @@
+use crate::AppError;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Ignore file with gitignore syntax for files that are tracked but unwanted in prompts.
+pub const PROMPT_IGNORE_FILENAME: &str = ".promptignore";
+
+/// Number of leading bytes inspected when checking whether a file is binary.
+pub const BINARY_SNIFF_LEN: u64 = 8192;
+
+/// Finds the project root containing `start`: the closest directory with a `.git`
+/// entry. Relative paths are stored relative to it, so the state is portable.
+pub fn project_root(start: &Path) -> Option<PathBuf> {
+    let start = fs::canonicalize(start).ok()?;
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Treats a file as binary if its first few KB contain a NUL byte, like git does.
+pub fn is_binary(path: &Path) -> Result<bool, AppError> {
+    let mut buffer = Vec::new();
+    fs::File::open(path)?
+        .take(BINARY_SNIFF_LEN)
+        .read_to_end(&mut buffer)?;
+    Ok(buffer.contains(&0))
+}
+
+/// Returns the hex-encoded SHA-256 of a file's contents.
+pub fn hash_file(path: &Path) -> Result<String, AppError> {
+    Ok(hash_bytes(&fs::read(path)?))
+}
+
+/// Returns the hex-encoded SHA-256 of `contents`.
+pub fn hash_bytes(contents: &[u8]) -> String {
+    let digest = Sha256::digest(contents);
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
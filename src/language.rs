@@ -0,0 +1,110 @@
+use std::path::Path;
+
+/// Maps lowercase file extensions to Markdown/highlighter language identifiers.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("bash", "bash"),
+    ("c", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("cs", "csharp"),
+    ("css", "css"),
+    ("cxx", "cpp"),
+    ("dart", "dart"),
+    ("ex", "elixir"),
+    ("exs", "elixir"),
+    ("go", "go"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("hs", "haskell"),
+    ("htm", "html"),
+    ("html", "html"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("json", "json"),
+    ("jsx", "jsx"),
+    ("kt", "kotlin"),
+    ("kts", "kotlin"),
+    ("lua", "lua"),
+    ("md", "markdown"),
+    ("mjs", "javascript"),
+    ("php", "php"),
+    ("pl", "perl"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("scala", "scala"),
+    ("scss", "scss"),
+    ("sh", "bash"),
+    ("sql", "sql"),
+    ("swift", "swift"),
+    ("toml", "toml"),
+    ("ts", "typescript"),
+    ("tsx", "tsx"),
+    ("txt", "text"),
+    ("xml", "xml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("zig", "zig"),
+    ("zsh", "bash"),
+];
+
+/// Files that are identified by their whole name rather than an extension.
+const FILE_NAMES: &[(&str, &str)] = &[("dockerfile", "dockerfile"), ("makefile", "makefile")];
+
+/// Returns the language identifier for `path`, based on its extension (or, for
+/// files like `Dockerfile`, its name). Matching is case-insensitive.
+pub fn language_for_path(path: &Path) -> Option<&'static str> {
+    let lookup = |table: &[(&str, &'static str)], key: &str| {
+        let key = key.to_ascii_lowercase();
+        table
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, language)| *language)
+    };
+    match path.extension() {
+        Some(extension) => lookup(EXTENSIONS, extension.to_str()?),
+        None => lookup(FILE_NAMES, path.file_name()?.to_str()?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_common_extensions() {
+        assert_eq!(language_for_path(Path::new("src/main.rs")), Some("rust"));
+        assert_eq!(language_for_path(Path::new("app.py")), Some("python"));
+        assert_eq!(language_for_path(Path::new("index.ts")), Some("typescript"));
+    }
+
+    #[test]
+    fn extensions_are_case_insensitive() {
+        assert_eq!(language_for_path(Path::new("MAIN.RS")), Some("rust"));
+        assert_eq!(language_for_path(Path::new("Script.Py")), Some("python"));
+        assert_eq!(
+            language_for_path(Path::new("Dockerfile")),
+            Some("dockerfile")
+        );
+        assert_eq!(language_for_path(Path::new("MAKEFILE")), Some("makefile"));
+    }
+
+    #[test]
+    fn compound_extensions_have_no_language() {
+        assert_eq!(language_for_path(Path::new("release.tar.gz")), None);
+    }
+
+    #[test]
+    fn unknown_files_have_no_language() {
+        assert_eq!(language_for_path(Path::new("photo.jpeg")), None);
+        assert_eq!(language_for_path(Path::new("LICENSE")), None);
+        assert_eq!(language_for_path(Path::new("")), None);
+    }
+
+    #[test]
+    fn tables_are_lowercase() {
+        for (key, _) in EXTENSIONS.iter().chain(FILE_NAMES) {
+            assert_eq!(*key, key.to_ascii_lowercase());
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! The core of `pb`: the state of collected files and rendering them into a prompt.
+//!
+//! ```no_run
+//! use prompt_builder_rs::{Prompt, RenderOptions};
+//!
+//! let prompt = Prompt::from_paths(["src"])?;
+//! print!("{}", prompt.render(&RenderOptions::default()));
+//! # Ok::<(), prompt_builder_rs::AppError>(())
+//! ```
+
+pub mod add;
+pub mod comments;
+mod error;
+pub mod escape;
+pub mod files;
+pub mod language;
+pub mod load;
+pub mod natural;
+pub mod print;
+pub mod prompt;
+pub mod redact;
+pub mod remote;
+pub mod select;
+mod state;
+pub mod stats;
+pub mod template;
+pub mod tokens;
+pub mod tree;
+
+pub use error::AppError;
+pub use prompt::{Format, Prompt, PromptFile, RenderOptions};
+pub use state::{AddedSource, FileEntry, LineRange, Source, State};
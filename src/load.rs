@@ -0,0 +1,228 @@
+use crate::comments;
+use crate::files::hash_bytes;
+use crate::redact::Redactor;
+use crate::{language, AppError, FileEntry, PromptFile};
+use std::path::Path;
+
+/// The transformations applied to a file's contents before it's rendered, matching
+/// the flags of `pb print`.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Remove comments in languages that support it
+    pub strip_comments: bool,
+    /// Prefix every line with its line number
+    pub line_numbers: bool,
+    /// Collapse runs of blank lines into a single blank line
+    pub squeeze_blank: bool,
+    /// Only keep the first this many lines of longer files
+    pub max_lines: Option<usize>,
+    /// Compute the hash of the file as it was read
+    pub hash: bool,
+}
+
+/// A file from the state together with its contents, ready to be rendered.
+#[derive(Debug, PartialEq)]
+pub struct LoadedFile<'a> {
+    pub entry: &'a FileEntry,
+    /// The contents after the transformations, with `truncated` set for `--max-lines`
+    pub prompt: PromptFile,
+    /// Number of secrets replaced because of `--redact`
+    pub redactions: usize,
+    /// Size of the file as it was read, before any transformations
+    pub size: u64,
+    /// Hash of the file as it was read, only computed if `LoadOptions::hash` is set
+    pub hash: Option<String>,
+    /// Problems that didn't stop the file from loading, for the caller to report
+    pub warnings: Vec<String>,
+}
+
+/// Reads a file's contents and applies the transformations requested in `options`.
+/// Invalid UTF-8 is replaced with U+FFFD and a leading byte order mark is removed.
+pub fn load_file<'a>(
+    entry: &'a FileEntry,
+    options: &LoadOptions,
+    redactor: Option<&Redactor>,
+) -> Result<LoadedFile<'a>, AppError> {
+    let bytes = entry.read()?;
+    let size = bytes.len() as u64;
+    let hash = options.hash.then(|| hash_bytes(&bytes));
+    let mut warnings = Vec::new();
+    let mut contents = String::from_utf8(bytes).unwrap_or_else(|e| {
+        warnings.push(format!(
+            "{} is not valid UTF-8, invalid sequences were replaced with U+FFFD",
+            entry.relative_path
+        ));
+        String::from_utf8_lossy(e.as_bytes()).into_owned()
+    });
+    // A byte order mark, as some Windows editors write, is noise in a prompt.
+    if let Some(without_bom) = contents.strip_prefix('\u{FEFF}') {
+        contents = without_bom.to_string();
+    }
+    let first_line = match entry.range {
+        Some((start, end)) => {
+            let line_count = contents.lines().count();
+            if end > line_count {
+                return Err(AppError::CustomError(format!(
+                    "Lines {}-{} of {} are out of range, it only has {} lines",
+                    start, end, entry.relative_path, line_count
+                )));
+            }
+            contents = contents
+                .split_inclusive('\n')
+                .skip(start - 1)
+                .take(end - start + 1)
+                .collect();
+            start
+        }
+        None => 1,
+    };
+    let language = language::language_for_path(Path::new(&entry.relative_path));
+    if options.strip_comments {
+        // With line numbers, lines that only had comments are kept so the numbers
+        // still match the file.
+        if let Some(stripped) =
+            language.and_then(|language| comments::strip(&contents, language, options.line_numbers))
+        {
+            contents = stripped;
+        }
+    }
+    let mut redactions = 0;
+    if let Some(redactor) = redactor {
+        let (redacted, count) = redactor.redact(&contents);
+        if count > 0 {
+            contents = redacted.into_owned();
+            redactions = count;
+        }
+    }
+    if options.squeeze_blank {
+        contents = squeeze_blank(&contents);
+    }
+    let mut omitted_lines = 0;
+    if let Some(max_lines) = options.max_lines {
+        omitted_lines = contents.lines().count().saturating_sub(max_lines);
+        if omitted_lines > 0 {
+            contents = truncate_lines(&contents, max_lines);
+        }
+    }
+    if options.line_numbers {
+        contents = number_lines(&contents, first_line);
+    }
+    if omitted_lines > 0 {
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        contents.push_str(&format!(
+            "... [truncated {} more lines]\n",
+            format_thousands(omitted_lines)
+        ));
+    }
+    Ok(LoadedFile {
+        entry,
+        prompt: PromptFile {
+            path: entry.display_path().to_string(),
+            contents,
+            lines: entry.range,
+            truncated: omitted_lines > 0,
+            // The name given with `--name` may not have an extension.
+            language,
+        },
+        redactions,
+        size,
+        hash,
+        warnings,
+    })
+}
+
+/// Collapses runs of two or more blank (empty or whitespace-only) lines into a
+/// single empty line.
+fn squeeze_blank(s: &str) -> String {
+    let mut squeezed = String::with_capacity(s.len());
+    let mut lines = s.split_inclusive('\n').peekable();
+    while let Some(line) = lines.next() {
+        let is_blank = |line: &str| line.trim().is_empty();
+        if !is_blank(line) || !lines.peek().is_some_and(|next| is_blank(next)) {
+            squeezed.push_str(line);
+            continue;
+        }
+        let mut last = line;
+        while let Some(next) = lines.next_if(|next| is_blank(next)) {
+            last = next;
+        }
+        // Keep the line ending of the run, unless it ended the file without one.
+        if last.ends_with("\r\n") {
+            squeezed.push_str("\r\n");
+        } else if last.ends_with('\n') {
+            squeezed.push('\n');
+        }
+    }
+    squeezed
+}
+
+/// Returns the first `max_lines` lines of `contents`, including their line endings.
+fn truncate_lines(contents: &str, max_lines: usize) -> String {
+    contents.split_inclusive('\n').take(max_lines).collect()
+}
+
+/// Prefixes every line with its right-aligned line number, e.g. `  42 | let x = 1;`,
+/// counting from `first_line`.
+fn number_lines(contents: &str, first_line: usize) -> String {
+    let width = (contents.lines().count() + first_line - 1)
+        .to_string()
+        .len();
+    let mut numbered = String::with_capacity(contents.len());
+    for (i, line) in contents.lines().enumerate() {
+        if line.is_empty() {
+            numbered.push_str(&format!("{:>width$} |\n", first_line + i));
+        } else {
+            numbered.push_str(&format!("{:>width$} | {}\n", first_line + i, line));
+        }
+    }
+    numbered
+}
+
+/// Formats `n` with `,` as the thousands separator, e.g. `12480` as `12,480`.
+pub fn format_thousands(n: impl std::fmt::Display) -> String {
+    let digits = n.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(c);
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squeeze_blank_collapses_runs_of_blank_lines() {
+        assert_eq!(squeeze_blank("a\n\n\n\nb\n"), "a\n\nb\n");
+        assert_eq!(squeeze_blank("a\n\nb\n"), "a\n\nb\n");
+        assert_eq!(squeeze_blank("a\nb\n"), "a\nb\n");
+    }
+
+    #[test]
+    fn squeeze_blank_collapses_leading_and_trailing_runs() {
+        assert_eq!(squeeze_blank("\n\n\na\n"), "\na\n");
+        assert_eq!(squeeze_blank("a\n\n\n"), "a\n\n");
+        // A run without a final line ending becomes a single empty last line.
+        assert_eq!(squeeze_blank("a\n\n\n\n  "), "a\n");
+        assert_eq!(squeeze_blank("\n\n"), "\n");
+        assert_eq!(squeeze_blank(""), "");
+    }
+
+    #[test]
+    fn squeeze_blank_treats_whitespace_only_lines_as_blank() {
+        assert_eq!(squeeze_blank("a\n  \n\t\n \t \nb\n"), "a\n\nb\n");
+        // A single whitespace-only line is kept as it is.
+        assert_eq!(squeeze_blank("a\n  \nb\n"), "a\n  \nb\n");
+    }
+
+    #[test]
+    fn squeeze_blank_keeps_crlf_line_endings() {
+        assert_eq!(squeeze_blank("a\r\n\r\n \r\nb\r\n"), "a\r\n\r\nb\r\n");
+    }
+}
@@ -1,28 +1,34 @@
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use directories::ProjectDirs;
-use ignore;
-use ignore::WalkBuilder;
-use serde::{Deserialize, Serialize};
+use rayon::prelude::*;
 use std::fs;
-use std::path::PathBuf;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
-use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum AppError {
-    #[error("Failed to read file: {0}")]
-    ReadError(#[from] std::io::Error),
-    #[error("Failed to parse state file: {0}")]
-    ParseError(#[from] serde_json::Error),
-    #[error("{0}")]
-    CustomError(String),
-    #[error("Failed file walker: {0}")]
-    GitIgnoreError(#[from] ignore::Error),
-}
+use prompt_builder_rs::add::{self, is_glob, AddOptions, AddSummary};
+use prompt_builder_rs::files::project_root;
+use prompt_builder_rs::load::{format_thousands, LoadOptions};
+use prompt_builder_rs::print::{self, Change, ContentUpdate, ManifestEntry, PrintOptions};
+use prompt_builder_rs::prompt::{Format, RenderOptions};
+use prompt_builder_rs::select::{select_files, EntryMatcher};
+use prompt_builder_rs::{
+    natural, remote, stats, tokens, tree, AddedSource, AppError, FileEntry, LineRange, Source,
+    State,
+};
+
+mod config;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Use a named profile with its own collection of files
+    #[arg(short, long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// Use this state file instead of the one in the config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    state: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -30,180 +36,2352 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Adds a file to the state
-    Add {
-        /// The relative path to the file to add
+    Add(AddArgs),
+    /// Adds the files staged in git
+    AddStaged,
+    /// Removes files from the state
+    Remove {
+        /// Relative paths or glob patterns of the files to remove
         #[arg(required = true, num_args = 1..)]
         files: Vec<String>,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Moves a file to another position in the state
+    Move {
+        /// The relative path of the file to move
+        path: String,
+        /// The new 1-based position of the file
+        to: usize,
     },
     /// Lists the files currently in the state
     List {
         #[arg(short, long)]
         long: bool,
+        /// Print the files as a JSON array, e.g. for `jq`
+        #[arg(long, conflicts_with = "long")]
+        json: bool,
+        /// Only list files with this tag
+        #[arg(long, value_name = "NAME")]
+        tag: Option<String>,
+        /// Only list files that no longer exist, i.e. the ones `prune` would remove
+        #[arg(long)]
+        missing: bool,
+        #[command(flatten)]
+        sort: SortArgs,
+    },
+    /// Adds new files from the paths that were previously added
+    Refresh {
+        /// Also remove files that no longer exist
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Shows which files a refresh would add and which files no longer exist
+    Diff {
+        /// Paths to walk instead of the ones that were previously added
+        paths: Vec<String>,
+    },
+    /// Searches the files in the state for lines matching a regex
+    Search {
+        /// The regex to search for
+        pattern: String,
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+    /// Shows the files in the state as a directory tree
+    Tree {
+        /// Only show this many directory levels, summarizing the files below them
+        #[arg(long, value_name = "N")]
+        max_depth: Option<usize>,
+    },
+    /// Opens the files in the state in your editor
+    Open {
+        /// Only open files matching this path or glob
+        pattern: Option<String>,
     },
     /// Clears the state
-    Clear,
+    Clear {
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Only remove the files with this tag
+        #[arg(long, value_name = "NAME")]
+        tag: Option<String>,
+    },
+    /// Removes files that no longer exist from the state
+    Prune {
+        #[command(flatten)]
+        report: ReportArgs,
+    },
+    /// Summarizes the size of the files in the state
+    Stats,
+    /// Checks that every file in the state can still be printed
+    Validate,
+    /// Prints just the number of files in the state, for scripts
+    Count {
+        /// Print the estimated number of tokens in the files instead
+        #[arg(long, conflicts_with = "bytes")]
+        tokens: bool,
+        /// Print the total size of the files in bytes instead
+        #[arg(long)]
+        bytes: bool,
+    },
     /// Prints the file contents
-    Print,
+    Print(PrintArgs),
+    /// Adds the files of another profile to the state
+    Merge {
+        /// The profile to merge files from
+        #[arg(value_name = "PROFILE")]
+        from: String,
+    },
+    /// Copies the state to another profile, to try out changes without touching this one
+    Copy {
+        /// The profile to copy to, or the path of a state file if it contains a `/` or
+        /// ends in `.json`
+        #[arg(value_name = "PROFILE|PATH")]
+        to: String,
+        /// Overwrite the destination if it already exists
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Writes the state to a file that can be shared and imported elsewhere
+    Export {
+        /// The file to write the state to
+        out: PathBuf,
+    },
+    /// Loads files from a state file written by `export`
+    Import {
+        /// The exported state file
+        file: PathBuf,
+        /// Add the imported files to the current state instead of replacing it
+        #[arg(long)]
+        merge: bool,
+    },
+    /// Manages the globs that every `add` to this profile skips
+    Excludes {
+        #[command(subcommand)]
+        command: ExcludesCommand,
+    },
+    /// Undoes the last clear, prune or remove by swapping in the backup
+    Restore,
     /// Prints details about this application
     Info,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-struct FileEntry {
-    relative_path: String,
-    absolute_path: PathBuf,
+#[derive(Subcommand, Debug)]
+enum ExcludesCommand {
+    /// Skips files matching these globs in every `add`, e.g. `excludes add 'fixtures/**'`
+    Add {
+        #[arg(required = true, num_args = 1..)]
+        globs: Vec<String>,
+    },
+    /// Lists the globs that every `add` skips
+    List,
+    /// Stops skipping files matching these globs
+    Remove {
+        #[arg(required = true, num_args = 1..)]
+        globs: Vec<String>,
+    },
+}
+
+#[derive(Args, Debug, Default)]
+struct AddArgs {
+    /// The relative path to the file to add, or `-` to read paths from stdin
+    #[arg(required_unless_present_any = ["from_file", "stdin"], num_args = 1..)]
+    files: Vec<String>,
+    /// Also add the newline-separated paths read from stdin
+    #[arg(long)]
+    stdin: bool,
+    /// Also add the paths listed in this file, one per line
+    #[arg(long, value_name = "PATH")]
+    from_file: Option<PathBuf>,
+    /// Skip files matching this glob (can be given multiple times)
+    #[arg(short, long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Skip files with these extensions, e.g. `--exclude-ext md,txt`
+    #[arg(
+        long,
+        value_name = "EXT",
+        value_delimiter = ',',
+        conflicts_with = "only_ext"
+    )]
+    exclude_ext: Vec<String>,
+    /// Only add files with these extensions, e.g. `--only-ext ts,tsx`
+    #[arg(long, value_name = "EXT", value_delimiter = ',')]
+    only_ext: Vec<String>,
+    /// Don't skip `*.lock` files
+    #[arg(long)]
+    include_lock: bool,
+    /// Don't respect `.promptignore`, `.gitignore`, `.ignore` and git exclude files
+    #[arg(long)]
+    no_ignore: bool,
+    /// Don't apply ignore files from the parent directories of the added paths
+    #[arg(long)]
+    no_parent_ignore: bool,
+    /// Include hidden files and directories
+    #[arg(long, overrides_with = "no_hidden")]
+    hidden: bool,
+    /// Skip hidden files and directories, even if the config file includes them
+    #[arg(long, overrides_with = "hidden")]
+    no_hidden: bool,
+    /// Add files that look binary instead of skipping them
+    #[arg(long)]
+    allow_binary: bool,
+    /// Skip files larger than this size, e.g. `100k` or `2m`
+    #[arg(long, value_name = "BYTES", value_parser = parse_size)]
+    max_size: Option<u64>,
+    /// Descend into symlinked directories
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// Only descend this many directory levels (1 = the directory's own files)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Only add files modified since this long ago or this date, e.g. `2d` or `2024-05-01`
+    #[arg(long, value_name = "DURATION|DATE", value_parser = parse_time)]
+    modified_after: Option<std::time::SystemTime>,
+    /// Only add files last modified before this long ago or this date
+    #[arg(long, value_name = "DURATION|DATE", value_parser = parse_time)]
+    modified_before: Option<std::time::SystemTime>,
+    /// Store paths relative to this directory instead of the project root
+    #[arg(long, value_name = "DIR")]
+    relative_to: Option<PathBuf>,
+    /// Store paths relative to the current directory instead of the project root
+    #[arg(long, conflicts_with = "relative_to")]
+    relative_to_cwd: bool,
+    /// Show the files that would be added, without changing the state
+    #[arg(long, conflicts_with_all = ["json", "quiet"])]
+    dry_run: bool,
+    /// Pick which of the new files to add from a list
+    #[arg(short, long, conflicts_with_all = ["dry_run", "stdin"])]
+    interactive: bool,
+    /// Update the stored hash, size, path and tags of files that are already in the state
+    #[arg(long)]
+    force: bool,
+    /// Fail instead of warning when a path or glob doesn't match any files
+    #[arg(long)]
+    strict: bool,
+    /// Skip files outside the project root instead of warning about them
+    #[arg(long)]
+    no_external: bool,
+    /// Show the file as this path in printed prompts, e.g. `config/app.toml`
+    #[arg(long, value_name = "LABEL")]
+    name: Option<String>,
+    /// Label the added files with this tag, e.g. `tests` or `api`
+    #[arg(long = "tag", value_name = "NAME")]
+    tags: Vec<String>,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+impl AddArgs {
+    /// The options for the library's `add_files`, with `--relative-to-cwd` resolved.
+    fn options(&self) -> Result<AddOptions, AppError> {
+        // Canonical, so that `refresh` stores the same paths from any directory.
+        let relative_to = match &self.relative_to {
+            Some(base) => Some(fs::canonicalize(base).map_err(|e| {
+                AppError::CustomError(format!("Invalid --relative-to {}: {}", base.display(), e))
+            })?),
+            None if self.relative_to_cwd => Some(fs::canonicalize(std::env::current_dir()?)?),
+            None => None,
+        };
+        Ok(AddOptions {
+            exclude: self.exclude.clone(),
+            exclude_ext: self.exclude_ext.clone(),
+            only_ext: self.only_ext.clone(),
+            include_lock: self.include_lock,
+            no_ignore: self.no_ignore,
+            no_parent_ignore: self.no_parent_ignore,
+            hidden: self.hidden,
+            allow_binary: self.allow_binary,
+            max_size: self.max_size,
+            follow_symlinks: self.follow_symlinks,
+            max_depth: self.max_depth,
+            relative_to,
+            modified_after: self.modified_after,
+            modified_before: self.modified_before,
+            no_external: self.no_external,
+            tags: self.tags.clone(),
+            force: self.force,
+            strict: self.strict,
+        })
+    }
+}
+
+/// How commands that change the state report what they did.
+#[derive(Args, Debug, Default, Clone, Copy)]
+struct ReportArgs {
+    /// Print a JSON object with the counts instead of messages
+    #[arg(long, conflicts_with = "quiet")]
+    json: bool,
+    /// Don't print any messages (warnings are still printed to stderr)
+    #[arg(short, long)]
+    quiet: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct State {
-    files: Vec<FileEntry>,
-    #[serde(skip)]
-    path: PathBuf,
+impl ReportArgs {
+    /// Whether messages meant for people should be printed.
+    fn messages(&self) -> bool {
+        !self.json && !self.quiet
+    }
+
+    /// Prints `summary` if --json was given.
+    fn json(&self, summary: serde_json::Value) {
+        if self.json {
+            println!("{}", summary);
+        }
+    }
 }
 
-impl State {
-    fn new(path: PathBuf) -> Result<Self, AppError> {
-        if path.exists() {
-            let contents = fs::read_to_string(&path)?;
-            if contents.trim().is_empty() {
-                return Ok(State {
-                    files: Vec::new(),
-                    path,
-                });
+#[derive(Args, Debug)]
+struct PrintArgs {
+    /// Only print files whose relative path matches one of these paths or globs
+    patterns: Vec<String>,
+    /// Only print files with this tag
+    #[arg(long, value_name = "NAME")]
+    tag: Option<String>,
+    /// Only print files that changed since they were added or last printed with this flag
+    #[arg(long)]
+    only_changed: bool,
+    /// Exit successfully without any output when there are no files to print
+    #[arg(long)]
+    allow_empty: bool,
+    #[command(flatten)]
+    sort: SortArgs,
+    /// Copy the prompt to the system clipboard instead of printing it
+    #[arg(long)]
+    clipboard: bool,
+    /// The format used to wrap the file contents
+    #[arg(long, value_enum, default_value_t = OutputFormat::Xml)]
+    format: OutputFormat,
+    /// Write the prompt to this file instead of printing it
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Prefix every line with its line number
+    #[arg(long, overrides_with = "no_line_numbers")]
+    line_numbers: bool,
+    /// Don't number lines, even if the config file turns line numbers on
+    #[arg(long, overrides_with = "line_numbers")]
+    no_line_numbers: bool,
+    /// Wrap files in a `<directory>` tag per top-level directory in XML output
+    #[arg(long)]
+    group_by_dir: bool,
+    /// List the paths of the printed files before their contents
+    #[arg(long)]
+    with_paths_header: bool,
+    /// Show a directory tree of the printed files before their contents
+    #[arg(long)]
+    with_tree: bool,
+    /// Start with a hint naming the most common programming language of the files
+    #[arg(long)]
+    detect_language: bool,
+    /// Collapse runs of blank lines into a single blank line
+    #[arg(long)]
+    squeeze_blank: bool,
+    /// Remove comments from files in languages that support it, e.g. Rust, Python and JavaScript
+    #[arg(long)]
+    strip_comments: bool,
+    /// Replace values that look like secrets (API keys, passwords, ...) with a placeholder
+    #[arg(long)]
+    redact: bool,
+    /// Only print the first N lines of longer files
+    #[arg(long, value_name = "N")]
+    max_lines: Option<usize>,
+    /// Name of the root tag wrapping all files in XML output
+    #[arg(long, value_name = "NAME", default_value = "files")]
+    root_tag: String,
+    /// Name of the tag wrapping each file in XML output
+    #[arg(long, value_name = "NAME", default_value = "file")]
+    file_tag: String,
+    /// System message to put before the files with --format chat
+    #[arg(long, value_name = "TEXT")]
+    system: Option<String>,
+    /// Build the prompt from this template, with `{{files}}` and `{{file:PATH}}` placeholders
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["prefix", "prefix_file", "suffix", "suffix_file"])]
+    template: Option<PathBuf>,
+    /// Text emitted verbatim before the files, e.g. a task description
+    #[arg(long, value_name = "TEXT", conflicts_with = "prefix_file")]
+    prefix: Option<String>,
+    /// Read the text emitted before the files from this file
+    #[arg(long, value_name = "PATH")]
+    prefix_file: Option<PathBuf>,
+    /// Text emitted verbatim after the files, e.g. closing instructions
+    #[arg(long, value_name = "TEXT", conflicts_with = "suffix_file")]
+    suffix: Option<String>,
+    /// Read the text emitted after the files from this file
+    #[arg(long, value_name = "PATH")]
+    suffix_file: Option<PathBuf>,
+    /// Don't XML-escape file contents and paths
+    #[arg(long)]
+    raw: bool,
+    /// Concatenate the file contents without any tags, each after a separator line
+    #[arg(long, conflicts_with_all = ["group_by_dir", "with_paths_header", "with_tree"])]
+    no_wrap: bool,
+    /// Separator line before each file with --no-wrap, `{path}` is replaced with its path
+    #[arg(long, value_name = "TEXT", requires = "no_wrap")]
+    separator: Option<String>,
+    /// Warn about files that can't be read and print the rest, instead of failing
+    #[arg(long)]
+    skip_missing: bool,
+    /// Count tokens with this model's tokenizer, e.g. `gpt-4o`
+    #[arg(long, value_name = "NAME")]
+    model: Option<String>,
+    /// Warn and exit with status 3 if the prompt has more tokens than this
+    #[arg(long, value_name = "N")]
+    max_tokens: Option<usize>,
+    /// Also write a JSON list of the printed files with their size and hash to this file
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+    /// Don't print the token, character, word and line counts to stderr
+    #[arg(long)]
+    no_summary: bool,
+    /// List every file's estimated tokens and share of the total on stderr, largest first
+    #[arg(long)]
+    breakdown: bool,
+    /// Don't output the prompt at all if it exceeds --max-tokens
+    #[arg(long, requires = "max_tokens")]
+    strict: bool,
+}
+
+/// Orders files differently than in the state, without changing the state.
+#[derive(Args, Debug)]
+struct SortArgs {
+    /// Order the files by this key instead of their order in the state
+    #[arg(long, value_enum, value_name = "KEY")]
+    sort: Option<SortKey>,
+    /// Reverse the order given by --sort
+    #[arg(long, requires = "sort")]
+    reverse: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum SortKey {
+    /// Relative path, with numbers compared by value (`file2` before `file10`)
+    Name,
+    /// File size, smallest first
+    Size,
+    /// Modification time, oldest first
+    Mtime,
+    /// When the file was added, oldest first
+    Added,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    /// `<file path="...">` tags inside a `<files>` root
+    Xml,
+    /// A heading and a fenced code block per file
+    Markdown,
+    /// A JSON array of chat messages, with the files in XML as the user message
+    Chat,
+    /// A collapsible `<details>` element per file, for embedding in a web page
+    Html,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, AppError> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Some(path) = config_path() {
+        apply_config(&mut cli.command, &matches, &config::Config::load(&path)?)?;
+    }
+    // An explicit state file takes precedence over everything, including profiles.
+    let (state_path, state_source) = match &cli.state {
+        Some(path) => (path.clone(), StateSource::Flag),
+        None => profile_state_path(cli.profile.as_deref())?,
+    };
+    let mut state = State::new(state_path)?;
+
+    match cli.command {
+        Commands::Add(args) => handle_add(&mut state, &args)?,
+        Commands::AddStaged => handle_add_staged(&mut state)?,
+        Commands::Remove { files, yes, report } => handle_remove(&mut state, files, yes, report)?,
+        Commands::Move { path, to } => handle_move(&mut state, &path, to)?,
+        Commands::List {
+            long,
+            json,
+            tag,
+            missing,
+            sort,
+        } => handle_list(&state, long, json, tag.as_deref(), missing, &sort)?,
+        Commands::Refresh { prune } => handle_refresh(&mut state, prune)?,
+        Commands::Diff { paths } => {
+            if handle_diff(&state, &paths)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Search {
+            pattern,
+            ignore_case,
+        } => {
+            if !handle_search(&state, &pattern, ignore_case)? {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Tree { max_depth } => handle_tree(&state, max_depth),
+        Commands::Open { pattern } => handle_open(&state, pattern)?,
+        Commands::Clear { yes, tag } => handle_clear(&mut state, yes, tag.as_deref())?,
+        Commands::Prune { report } => handle_prune(&mut state, report)?,
+        Commands::Stats => handle_stats(&state),
+        Commands::Validate => {
+            if !handle_validate(&state)? {
+                return Ok(ExitCode::FAILURE);
             }
-            let mut state: State = serde_json::from_str(&contents)?;
-            state.path = path;
-            Ok(state)
-        } else {
-            Ok(State {
-                files: Vec::new(),
-                path,
-            })
         }
+        Commands::Count { tokens, bytes } => handle_count(&state, tokens, bytes),
+        Commands::Print(args) => return handle_print(&mut state, &args),
+        Commands::Merge { from } => handle_merge(&mut state, &from)?,
+        Commands::Copy { to, force } => handle_copy(&state, &to, force)?,
+        Commands::Export { out } => handle_export(&state, &out)?,
+        Commands::Import { file, merge } => handle_import(&mut state, &file, merge)?,
+        Commands::Excludes { command } => handle_excludes(&mut state, command)?,
+        Commands::Restore => handle_restore(&state)?,
+        Commands::Info => handle_info(&state, state_source, cli.profile.as_deref())?,
     }
+    Ok(ExitCode::SUCCESS)
+}
 
-    fn save(&self) -> Result<(), AppError> {
-        if let Some(parent) = self.path.parent() {
-            fs::create_dir_all(parent)?;
+fn handle_info(state: &State, source: StateSource, profile: Option<&str>) -> Result<(), AppError> {
+    println!("State path: {} (from the {})", state.path.display(), source);
+    println!(
+        "State file: {}",
+        if state.path.exists() {
+            "exists"
+        } else {
+            "not created yet"
         }
-        let contents = serde_json::to_string_pretty(self)?;
-        fs::write(&self.path, contents)?;
-        Ok(())
+    );
+    match (source, profile) {
+        (StateSource::Flag, Some(profile)) => {
+            println!("Profile: {} (ignored because of --state)", profile)
+        }
+        (StateSource::Flag, None) => println!("Profile: none"),
+        (_, profile) => println!("Profile: {}", profile.unwrap_or("default")),
+    }
+    match config_path() {
+        Some(path) if path.exists() => println!("Config file: {}", path.display()),
+        Some(path) => println!("Config file: {} (not created yet)", path.display()),
+        None => println!("Config file: none"),
     }
+    match project_root(&std::env::current_dir()?) {
+        Some(root) => println!("Project root: {}", root.display()),
+        None => println!("Project root: none (paths are relative to the current directory)"),
+    }
+    let total_bytes: u64 = state
+        .files
+        .iter()
+        .filter_map(|file| fs::metadata(file.local_path()?).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    println!("Files: {}", format_thousands(state.files.len()));
+    println!("Total size: {} bytes", format_thousands(total_bytes));
+    Ok(())
 }
 
-fn main() -> ExitCode {
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        return ExitCode::FAILURE;
+/// Environment variable overriding the path of the default state file.
+const STATE_ENV_VAR: &str = "PROMPT_BUILDER_STATE";
+
+/// Where the path of the state file came from.
+#[derive(Clone, Copy, Debug)]
+enum StateSource {
+    Flag,
+    EnvVar,
+    ConfigDir,
+    CurrentDir,
+}
+
+impl std::fmt::Display for StateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StateSource::Flag => write!(f, "--state option"),
+            StateSource::EnvVar => write!(f, "{} environment variable", STATE_ENV_VAR),
+            StateSource::ConfigDir => write!(f, "config directory"),
+            StateSource::CurrentDir => write!(f, "current directory"),
+        }
     }
-    ExitCode::SUCCESS
 }
 
-fn run() -> Result<(), AppError> {
-    let cli = Cli::parse();
-    let state_path = ProjectDirs::from("org", "sweb", "PromptBuilder")
-        .map(|proj_dirs| proj_dirs.config_dir().join("state.json"))
+/// Returns the path of the state file for the given profile: `PROMPT_BUILDER_STATE`
+/// if it's set, otherwise the file in the config directory, otherwise the file in
+/// the current directory.
+fn profile_state_path(profile: Option<&str>) -> Result<(PathBuf, StateSource), AppError> {
+    let state_file = state_file_name(profile)?;
+    if let Some(path) = std::env::var_os(STATE_ENV_VAR).filter(|path| !path.is_empty()) {
+        let path = PathBuf::from(path);
+        // Named profiles are stored next to the file named by the variable.
+        let path = match profile {
+            None => path,
+            Some(_) => path.with_file_name(&state_file),
+        };
+        return Ok((path, StateSource::EnvVar));
+    }
+    Ok(ProjectDirs::from("org", "sweb", "PromptBuilder")
+        .map(|proj_dirs| {
+            (
+                proj_dirs.config_dir().join(&state_file),
+                StateSource::ConfigDir,
+            )
+        })
         .unwrap_or_else(|| {
             eprintln!(
                 "Warning: Could not determine config directory. Using current directory for state"
             );
-            PathBuf::from("state.json")
-        });
-    let mut state = State::new(state_path)?;
+            (PathBuf::from(&state_file), StateSource::CurrentDir)
+        }))
+}
 
-    match cli.command {
-        Commands::Add { files } => handle_add(&mut state, files)?,
-        Commands::List { long } => handle_list(&state, long),
-        Commands::Clear => handle_clear(&mut state)?,
-        Commands::Print => handle_print(&state)?,
-        Commands::Info => {
-            println!("State path: {}", state.path.display());
+/// Returns the path of `config.toml` in the config directory, if there is one.
+fn config_path() -> Option<PathBuf> {
+    ProjectDirs::from("org", "sweb", "PromptBuilder")
+        .map(|proj_dirs| proj_dirs.config_dir().join("config.toml"))
+}
+
+/// Fills in the flags that weren't given on the command line from the config file.
+fn apply_config(
+    command: &mut Commands,
+    matches: &ArgMatches,
+    config: &config::Config,
+) -> Result<(), AppError> {
+    let (_, args) = matches.subcommand().expect("a subcommand is required");
+    let from_config = |id: &str| args.value_source(id) != Some(ValueSource::CommandLine);
+    let invalid = |key: &str, e: String| {
+        AppError::CustomError(format!("Invalid {} in config file: {}", key, e))
+    };
+    match command {
+        Commands::Add(add) => {
+            if let Some(exclude) = config
+                .add
+                .exclude
+                .as_ref()
+                .filter(|_| from_config("exclude"))
+            {
+                add.exclude = exclude.clone();
+            }
+            if let Some(max_size) = config
+                .add
+                .max_size
+                .as_ref()
+                .filter(|_| from_config("max_size"))
+            {
+                add.max_size = Some(parse_size(max_size).map_err(|e| invalid("add.max_size", e))?);
+            }
+            if from_config("hidden") && from_config("no_hidden") {
+                add.hidden = config.add.hidden.unwrap_or(add.hidden);
+            }
         }
+        Commands::Print(print) => {
+            if let Some(format) = config
+                .print
+                .format
+                .as_ref()
+                .filter(|_| from_config("format"))
+            {
+                print.format =
+                    OutputFormat::from_str(format, true).map_err(|e| invalid("print.format", e))?;
+            }
+            if from_config("line_numbers") && from_config("no_line_numbers") {
+                print.line_numbers = config.print.line_numbers.unwrap_or(print.line_numbers);
+            }
+        }
+        _ => {}
     }
     Ok(())
 }
 
-fn handle_add(state: &mut State, patterns: Vec<String>) -> Result<(), AppError> {
-    let mut builder = WalkBuilder::new(&patterns[0]);
+/// Returns the name of the state file for the given profile.
+fn state_file_name(profile: Option<&str>) -> Result<String, AppError> {
+    match profile {
+        None => Ok("state.json".into()),
+        Some(name)
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            Ok(format!("state-{}.json", name))
+        }
+        Some(name) => Err(AppError::CustomError(format!(
+            "Invalid profile name '{}': only letters, digits, '-' and '_' are allowed",
+            name
+        ))),
+    }
+}
 
-    let mut override_builder = ignore::overrides::OverrideBuilder::new(&patterns[0]);
-    override_builder.add("!*.lock")?;
-    for pattern in patterns.iter().skip(1) {
-        builder.add(pattern);
+fn handle_add(state: &mut State, args: &AddArgs) -> Result<(), AppError> {
+    let named_path = match &args.name {
+        Some(_) => Some(named_path(args)?),
+        None => None,
+    };
+    if args.interactive && !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Err(AppError::CustomError(
+            "--interactive needs a terminal to show the file selection".into(),
+        ));
+    }
+    // A dry run goes through the same steps on a copy of the state, and shows the
+    // files that were added to the copy.
+    let mut preview;
+    let state = if args.dry_run {
+        preview = State {
+            files: state.files.clone(),
+            sources: state.sources.clone(),
+            default_excludes: state.default_excludes.clone(),
+            ..Default::default()
+        };
+        &mut preview
+    } else {
+        state
+    };
+    let options = args.options()?;
+    let initial_count = state.files.len();
+    let mut patterns: Vec<String> = args.files.iter().filter(|f| *f != "-").cloned().collect();
+    if args.stdin || args.files.iter().any(|f| f == "-") {
+        for line in std::io::stdin().lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                patterns.push(line.trim().to_string());
+            }
+        }
     }
-    let overrides = override_builder.build()?;
-    builder.overrides(overrides);
+    if let Some(manifest) = &args.from_file {
+        patterns.extend(read_manifest(manifest)?);
+    }
+    // Arguments are added in the order they were given, which is the order `print`
+    // shows them in. Runs of plain paths are walked together.
+    let mut arguments = Vec::new();
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        let argument = if remote::is_url(&pattern) {
+            AddArgument::Url(pattern)
+        } else if let Some((path, range)) = line_range(&pattern)? {
+            AddArgument::Ranged(path, range)
+        } else {
+            paths.push(pattern.clone());
+            if let Some(AddArgument::Paths(run)) = arguments.last_mut() {
+                run.push(pattern);
+                continue;
+            }
+            AddArgument::Paths(vec![pattern])
+        };
+        arguments.push(argument);
+    }
+    if arguments.is_empty() {
+        report_added(&AddSummary::default(), state.files.len(), &args.report);
+        return Ok(());
+    }
+    let mut summary = AddSummary::default();
+    // Whether a range or name of a file that was already in the state changed.
+    let mut entries_changed = false;
+    for argument in arguments {
+        let (path, range) = match argument {
+            AddArgument::Paths(run) => {
+                summary.merge(add::add_files(state, &run, &options)?);
+                continue;
+            }
+            AddArgument::Url(url) => {
+                summary.merge(add::add_remote_files(
+                    state,
+                    std::slice::from_ref(&url),
+                    &options,
+                )?);
+                continue;
+            }
+            AddArgument::Ranged(path, range) => (path, range),
+        };
+        let added = state.files.len();
+        summary.merge(add::add_files(
+            state,
+            std::slice::from_ref(&path),
+            &options,
+        )?);
+        if state.files.len() > added {
+            state.files[added].range = Some(range);
+        } else if let Some(entry) = fs::canonicalize(&path).ok().and_then(|path| {
+            state
+                .files
+                .iter_mut()
+                .find(|file| file.local_path() == Some(&path))
+        }) {
+            // Adding a file that is already in the state again updates its range.
+            if args.report.messages() {
+                println!(
+                    "{} the line range of {} to {}-{}.",
+                    if args.dry_run {
+                        "Would update"
+                    } else {
+                        "Updated"
+                    },
+                    entry.relative_path,
+                    range.0,
+                    range.1
+                );
+            }
+            entry.range = Some(range);
+            entries_changed = true;
+        }
+    }
+    if let (Some(name), Some(path)) = (&args.name, &named_path) {
+        let absolute_path = fs::canonicalize(path).ok();
+        if let Some(index) = state.files.iter().position(|file| {
+            file.relative_path == *path
+                || absolute_path.is_some() && file.local_path() == absolute_path.as_deref()
+        }) {
+            let entry = &mut state.files[index];
+            // Naming a file that is already in the state again renames it.
+            if index < initial_count && entry.display_name.as_ref() != Some(name) {
+                entries_changed = true;
+                if args.report.messages() {
+                    println!(
+                        "{} the name of {} to {}.",
+                        if args.dry_run {
+                            "Would update"
+                        } else {
+                            "Updated"
+                        },
+                        entry.relative_path,
+                        name
+                    );
+                }
+            }
+            entry.display_name = Some(name.clone());
+        }
+    }
+    if args.dry_run {
+        for file in &state.files[initial_count..] {
+            println!("+ {}", file.relative_path);
+        }
+        if args.force {
+            println!(
+                "Would add {} new file(s), update {} already present.",
+                summary.added, summary.updated
+            );
+        } else {
+            println!(
+                "Would add {} new file(s), {} already present.",
+                summary.added, summary.already_present
+            );
+        }
+        return Ok(());
+    }
+    if args.interactive {
+        let new_files = state.files.split_off(initial_count);
+        let selected = if new_files.is_empty() {
+            Vec::new()
+        } else {
+            let paths: Vec<_> = new_files.iter().map(|f| f.relative_path.as_str()).collect();
+            let Some(selected) = dialoguer::MultiSelect::new()
+                .with_prompt(
+                    "Select the files to add (space toggles, a toggles all, enter confirms)",
+                )
+                .items(&paths)
+                .max_length(20)
+                .interact_opt()
+                .map_err(|e| {
+                    AppError::CustomError(format!("Failed to show the file selection: {}", e))
+                })?
+            else {
+                println!("Cancelled, the state wasn't changed.");
+                return Ok(());
+            };
+            selected
+        };
+        summary.added = selected.len();
+        for (i, file) in new_files.into_iter().enumerate() {
+            if selected.contains(&i) {
+                state.files.push(file);
+            }
+        }
+        // The arguments aren't remembered for `refresh`, which would add the files
+        // that weren't selected.
+        if summary.added > 0 || summary.updated > 0 || entries_changed {
+            state.save()?;
+        }
+        report_added(&summary, state.files.len(), &args.report);
+        return Ok(());
+    }
+    let mut sources_changed = false;
+    for pattern in &paths {
+        // Globs are stored as absolute patterns so `refresh` works from anywhere.
+        let source = if is_glob(pattern) && !Path::new(pattern).exists() {
+            std::env::current_dir()?
+                .join(pattern)
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            // A path that didn't match anything was warned about.
+            let Ok(path) = fs::canonicalize(pattern) else {
+                continue;
+            };
+            path.to_string_lossy().into_owned()
+        };
+        // The options of the latest add of a path are the ones `refresh` uses.
+        let source = AddedSource {
+            path: source,
+            options: AddOptions {
+                force: false,
+                strict: false,
+                ..options.clone()
+            },
+        };
+        match state.sources.iter_mut().find(|s| s.path == source.path) {
+            Some(existing) if *existing == source => {}
+            Some(existing) => {
+                *existing = source;
+                sources_changed = true;
+            }
+            None => {
+                state.sources.push(source);
+                sources_changed = true;
+            }
+        }
+    }
+    if summary.added > 0 || summary.updated > 0 || sources_changed || entries_changed {
+        state.save()?;
+    }
+    report_added(&summary, state.files.len(), &args.report);
+    Ok(())
+}
 
-    let mut added_count = 0;
+/// An argument of `add`, see `handle_add`.
+enum AddArgument {
+    /// Consecutive paths, directories or globs
+    Paths(Vec<String>),
+    Url(String),
+    /// A `path:START-END` argument
+    Ranged(String, LineRange),
+}
+
+/// Prints the summary of an add as requested by `report`. `total` is the number of
+/// files in the state afterwards.
+fn report_added(summary: &AddSummary, total: usize, report: &ReportArgs) {
+    report.json(serde_json::json!({
+        "added": summary.added,
+        "updated": summary.updated,
+        "already_present": summary.already_present,
+        "skipped": summary.skipped(),
+        "total": total,
+    }));
+    if report.messages() {
+        print!("{}", summary);
+    }
+}
+
+/// The path or URL of the single file that `add --name` names.
+fn named_path(args: &AddArgs) -> Result<String, AppError> {
+    let single =
+        || AppError::CustomError("--name can only be used when adding a single file".into());
+    let [arg] = args.files.as_slice() else {
+        return Err(single());
+    };
+    if arg == "-" || args.stdin || args.from_file.is_some() {
+        return Err(single());
+    }
+    if remote::is_url(arg) {
+        return Ok(arg.clone());
+    }
+    let path = line_range(arg)?.map_or_else(|| arg.clone(), |(path, _)| path);
+    if !Path::new(&path).is_file() {
+        return Err(single());
+    }
+    Ok(path)
+}
+
+/// Splits a `path:START-END` argument into the path and its line range. Arguments
+/// that name an existing file, or don't end in a range, aren't ranges.
+fn line_range(arg: &str) -> Result<Option<(String, LineRange)>, AppError> {
+    if Path::new(arg).exists() {
+        return Ok(None);
+    }
+    let Some((path, range)) = arg.rsplit_once(':') else {
+        return Ok(None);
+    };
+    let Some((start, end)) = range.split_once('-') else {
+        return Ok(None);
+    };
+    let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) else {
+        return Ok(None);
+    };
+    if start == 0 || start > end {
+        return Err(AppError::CustomError(format!(
+            "Invalid line range '{}-{}' for {}: lines start at 1 and the start can't be after the end",
+            start, end, path
+        )));
+    }
+    Ok(Some((path.to_string(), (start, end))))
+}
+
+fn handle_add_staged(state: &mut State) -> Result<(), AppError> {
+    let toplevel = PathBuf::from(git(&["rev-parse", "--show-toplevel"])?.trim_end());
+    // Deleted files are filtered out, and renames are listed under their new name.
+    let staged = git(&["diff", "--cached", "--name-only", "--diff-filter=d", "-z"])?;
+    let paths: Vec<String> = staged
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(|path| toplevel.join(path).to_string_lossy().into_owned())
+        .collect();
+    if paths.is_empty() {
+        println!("No staged files to add.");
+        return Ok(());
+    }
+    let summary = add::add_files(state, &paths, &AddOptions::default())?;
+    if summary.added > 0 {
+        state.save()?;
+    }
+    print!("{}", summary);
+    Ok(())
+}
+
+/// Runs git with the given arguments and returns its stdout.
+fn git(args: &[&str]) -> Result<String, AppError> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| AppError::CustomError(format!("Failed to run git: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(AppError::CustomError(
+            if stderr.contains("not a git repository") {
+                "The current directory is not inside a git repository".into()
+            } else {
+                format!("git {} failed: {}", args.join(" "), stderr.trim())
+            },
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the paths listed in a manifest file. Blank lines and `#` comments are
+/// skipped, and relative paths are resolved against the manifest's directory.
+fn read_manifest(manifest: &Path) -> Result<Vec<String>, AppError> {
+    let base = manifest.parent().unwrap_or(Path::new(""));
+    Ok(fs::read_to_string(manifest)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line).to_string_lossy().into_owned())
+        .collect())
+}
+
+fn handle_refresh(state: &mut State, prune: bool) -> Result<(), AppError> {
+    if state.sources.is_empty() {
+        println!("No added paths to refresh.");
+        return Ok(());
+    }
+    // Every path is walked with the options it was added with, e.g. its excludes.
+    let mut summary = AddSummary::default();
+    for source in state.sources.clone() {
+        if !is_glob(&source.path) && !Path::new(&source.path).exists() {
+            eprintln!("Warning: {} no longer exists. Skipping it.", source.path);
+            continue;
+        }
+        summary.merge(add::add_files(
+            state,
+            std::slice::from_ref(&source.path),
+            &source.options,
+        )?);
+    }
+    let pruned = if prune {
+        prune_missing(state)
+    } else {
+        Vec::new()
+    };
+    if !pruned.is_empty() {
+        state.backup();
+    }
+    if summary.added > 0 || !pruned.is_empty() {
+        state.save()?;
+    }
+    print!("{}", summary);
+    if prune {
+        println!("{} missing file(s) pruned.", pruned.len());
+    }
+    Ok(())
+}
 
-    let existing_paths: std::collections::HashSet<_> = state
+/// Prints the differences between the state and a fresh walk of `paths` (or the
+/// previously added paths), without changing the state. Returns whether there were any.
+fn handle_diff(state: &State, paths: &[String]) -> Result<bool, AppError> {
+    // Paths are walked with the options they were added with, like `refresh` does.
+    // Other paths given on the command line get the default options.
+    let sources: Vec<AddedSource> = if paths.is_empty() {
+        state
+            .sources
+            .iter()
+            .filter(|source| is_glob(&source.path) || Path::new(&source.path).exists())
+            .cloned()
+            .collect()
+    } else {
+        paths
+            .iter()
+            .map(|path| {
+                let absolute = fs::canonicalize(path)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .ok();
+                state
+                    .sources
+                    .iter()
+                    .find(|source| Some(&source.path) == absolute.as_ref())
+                    .map(|source| AddedSource {
+                        path: path.clone(),
+                        options: source.options.clone(),
+                    })
+                    .unwrap_or_else(|| AddedSource {
+                        path: path.clone(),
+                        options: AddOptions::default(),
+                    })
+            })
+            .collect()
+    };
+    // Walking into a copy of the state finds the same files `refresh` would add.
+    let mut fresh = State {
+        files: state.files.clone(),
+        default_excludes: state.default_excludes.clone(),
+        ..Default::default()
+    };
+    for source in &sources {
+        add::add_files(
+            &mut fresh,
+            std::slice::from_ref(&source.path),
+            &source.options,
+        )?;
+    }
+    let added = &fresh.files[state.files.len()..];
+    let removed: Vec<_> = state.files.iter().filter(|file| !file.exists()).collect();
+    if added.is_empty() && removed.is_empty() {
+        println!("No differences.");
+        return Ok(false);
+    }
+    if !added.is_empty() {
+        println!("New files ({}):", added.len());
+        for file in added {
+            println!("+ {}", file.relative_path);
+        }
+    }
+    if !removed.is_empty() {
+        if !added.is_empty() {
+            println!();
+        }
+        println!("Missing files ({}):", removed.len());
+        for file in removed {
+            println!("- {}", file.relative_path);
+        }
+    }
+    Ok(true)
+}
+
+/// Parses a human-readable size such as `512`, `100k`, `2m` or `1G` into bytes.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let digits = lower.strip_suffix('b').unwrap_or(&lower);
+    let (digits, multiplier) = match digits.chars().last() {
+        Some('k') => (&digits[..digits.len() - 1], 1024),
+        Some('m') => (&digits[..digits.len() - 1], 1024 * 1024),
+        Some('g') => (&digits[..digits.len() - 1], 1024 * 1024 * 1024),
+        _ => (digits, 1),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size '{}', expected e.g. 512, 100k or 2m", s))
+}
+
+/// Parses a point in time given either as a duration before now, such as `2d`,
+/// `3h` or `1week 2days`, or as a date or timestamp in UTC, such as `2024-05-01`
+/// or `2024-05-01 12:00:00`.
+fn parse_time(s: &str) -> Result<std::time::SystemTime, String> {
+    let s = s.trim();
+    if let Ok(duration) = humantime::parse_duration(s) {
+        return std::time::SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| format!("duration '{}' is too long", s));
+    }
+    humantime::parse_rfc3339_weak(s)
+        .or_else(|_| humantime::parse_rfc3339_weak(&format!("{} 00:00:00", s)))
+        .map_err(|_| {
+            format!(
+                "invalid time '{}', expected a duration like 2d or 3h, or a date like 2024-05-01",
+                s
+            )
+        })
+}
+
+fn handle_remove(
+    state: &mut State,
+    patterns: Vec<String>,
+    yes: bool,
+    report: ReportArgs,
+) -> Result<(), AppError> {
+    let matchers = patterns
+        .iter()
+        .map(|pattern| EntryMatcher::new(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut matched = vec![false; matchers.len()];
+    let removed: Vec<bool> = state
         .files
         .iter()
-        .map(|f| f.absolute_path.clone())
+        .map(|file| {
+            let mut remove = false;
+            for (i, matcher) in matchers.iter().enumerate() {
+                if matcher.is_match(file) {
+                    matched[i] = true;
+                    remove = true;
+                }
+            }
+            remove
+        })
         .collect();
-    for result in builder.build() {
-        let entry = result?;
-        let file_path = entry.path();
-        if file_path.is_file() {
-            let absolute_path = fs::canonicalize(file_path)?;
-            if !existing_paths.contains(&absolute_path) {
-                let entry = FileEntry {
-                    relative_path: file_path.to_string_lossy().into(),
-                    absolute_path,
+    let removed_count = removed.iter().filter(|&&remove| remove).count();
+
+    for (pattern, matched) in patterns.iter().zip(matched) {
+        if !matched {
+            eprintln!("Warning: '{}' did not match any files in state.", pattern);
+        }
+    }
+    // Scripts aren't asked, so that removing stays usable without a terminal.
+    if removed_count > 0 && !yes && std::io::stdin().is_terminal() {
+        println!("Files to remove:");
+        for (file, _) in state
+            .files
+            .iter()
+            .zip(&removed)
+            .filter(|(_, &remove)| remove)
+        {
+            println!("- {}", file.relative_path);
+        }
+        if !confirm(&format!("Remove {} files from state?", removed_count))? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+    let mut removed = removed.into_iter();
+    state.files.retain(|_| !removed.next().unwrap_or(false));
+    if removed_count > 0 {
+        state.backup();
+        state.save()?;
+    }
+    report.json(serde_json::json!({
+        "removed": removed_count,
+        "total": state.files.len(),
+    }));
+    if report.messages() {
+        if removed_count > 0 {
+            println!("{} file(s) removed successfully.", removed_count);
+        } else {
+            println!("No files removed.")
+        }
+    }
+    Ok(())
+}
+
+fn handle_move(state: &mut State, path: &str, to: usize) -> Result<(), AppError> {
+    let absolute_path = fs::canonicalize(path).ok();
+    let from = state
+        .files
+        .iter()
+        .position(|file| {
+            file.relative_path == path
+                || absolute_path.is_some() && absolute_path.as_deref() == file.local_path()
+        })
+        .ok_or_else(|| AppError::CustomError(format!("'{}' is not in the state", path)))?;
+    if to == 0 || to > state.files.len() {
+        return Err(AppError::CustomError(format!(
+            "Position {} is out of range, expected 1 to {}",
+            to,
+            state.files.len()
+        )));
+    }
+    let file = state.files.remove(from);
+    println!("Moved {} to position {}.", file.relative_path, to);
+    state.files.insert(to - 1, file);
+    state.renumber();
+    state.save()?;
+    Ok(())
+}
+
+fn handle_list(
+    state: &State,
+    long: bool,
+    json: bool,
+    tag: Option<&str>,
+    missing: bool,
+    sort: &SortArgs,
+) -> Result<(), AppError> {
+    // Positions refer to the whole state, so they stay valid for `move`.
+    let mut files: Vec<_> = (1..)
+        .zip(&state.files)
+        .filter(|(_, file)| file.has_tag(tag) && !(missing && file.exists()))
+        .collect();
+    sort_files(&mut files, sort, |(_, file)| file);
+    let heading = if missing {
+        "Missing files:"
+    } else {
+        "Files in state:"
+    };
+    if json {
+        let entries: Vec<_> = files.iter().map(|(_, file)| file).collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if state.files.is_empty() {
+        println!("No files have been added yet.");
+    } else if let (true, Some(tag)) = (files.is_empty(), tag) {
+        println!("No files are tagged '{}'.", tag);
+    } else if missing && files.is_empty() {
+        println!("All files present.");
+    } else if long {
+        println!("{}", heading);
+        let rows: Vec<_> = files
+            .iter()
+            .map(|&(position, file)| {
+                // Remote files aren't downloaded just to list them.
+                let (size, lines) = match file.local_path().map(fs::read) {
+                    Some(Ok(contents)) => (
+                        format_thousands(contents.len()),
+                        format_thousands(stats::count_lines(&contents)),
+                    ),
+                    _ => ("?".into(), "?".into()),
                 };
-                state.files.push(entry);
-                added_count += 1;
+                let added = file.added_at.as_deref().unwrap_or("unknown").to_string();
+                (position, file, size, lines, added)
+            })
+            .collect();
+        let index_width = state.files.len().to_string().len();
+        let path_width = column_width(rows.iter().map(|(_, file, ..)| &file.relative_path));
+        let size_width = column_width(rows.iter().map(|(_, _, size, ..)| size));
+        let lines_width = column_width(rows.iter().map(|(_, _, _, lines, _)| lines));
+        let added_width = column_width(rows.iter().map(|(.., added)| added));
+        for (position, file, size, lines, added) in &rows {
+            let name = match &file.display_name {
+                Some(name) => format!("  as {}", name),
+                None => String::new(),
+            };
+            let tags = if file.tags.is_empty() {
+                String::new()
+            } else {
+                format!("  [{}]", file.tags.join(", "))
+            };
+            println!(
+                "{:>index_width$}. {:<path_width$}  {:>size_width$} bytes  {:>lines_width$} lines  added {:<added_width$}  ({}){}{}",
+                position,
+                file.relative_path,
+                size,
+                lines,
+                added,
+                file.location(),
+                name,
+                tags
+            );
+        }
+    } else {
+        println!("{}", heading);
+        for (_, file) in &files {
+            println!("- {}", file.relative_path)
+        }
+    }
+    Ok(())
+}
+
+/// Sorts `items` as requested in `sort`. Files whose metadata can't be read are
+/// sorted last, in their original order.
+fn sort_files<T>(items: &mut Vec<T>, sort: &SortArgs, entry: impl Fn(&T) -> &FileEntry) {
+    let Some(key) = sort.sort else {
+        return;
+    };
+    if key == SortKey::Name {
+        items.sort_by(|a, b| natural::compare(&entry(a).relative_path, &entry(b).relative_path));
+        if sort.reverse {
+            items.reverse();
+        }
+        return;
+    }
+    let mut keyed: Vec<_> = items
+        .drain(..)
+        .map(|item| {
+            let file = entry(&item);
+            // Files added before the time was recorded are sorted last.
+            if key == SortKey::Added {
+                let added = file
+                    .added_at
+                    .as_deref()
+                    .and_then(|added| humantime::parse_rfc3339(added).ok())
+                    .map(|added| {
+                        added
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_or(0, |duration| duration.as_nanos())
+                    });
+                return (added, item);
+            }
+            // Remote files have no size or modification time, they are sorted last.
+            let Some(path) = file.local_path() else {
+                return (None, item);
+            };
+            let metadata = fs::metadata(path);
+            let value = match key {
+                SortKey::Size => metadata.map(|metadata| u128::from(metadata.len())),
+                _ => metadata
+                    .and_then(|metadata| metadata.modified())
+                    .map(|modified| {
+                        modified
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_or(0, |duration| duration.as_nanos())
+                    }),
+            };
+            let value = value
+                .inspect_err(|e| {
+                    eprintln!(
+                        "Warning: Failed to read metadata of {}: {}. Sorting it last.",
+                        file.relative_path, e
+                    )
+                })
+                .ok();
+            (value, item)
+        })
+        .collect();
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if sort.reverse => b.cmp(a),
+        (Some(a), Some(b)) => a.cmp(b),
+        // `None` sorts after `Some`, regardless of --reverse.
+        _ => b.is_some().cmp(&a.is_some()),
+    });
+    items.extend(keyed.into_iter().map(|(_, item)| item));
+}
+
+fn column_width<'a>(values: impl Iterator<Item = &'a String>) -> usize {
+    values.map(|value| value.chars().count()).max().unwrap_or(0)
+}
+
+/// Prints every matching line as `path:line: text`. Returns whether anything matched.
+fn handle_search(state: &State, pattern: &str, ignore_case: bool) -> Result<bool, AppError> {
+    let regex = regex::RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()?;
+    let mut found = false;
+    for file in &state.files {
+        let contents = match file.read() {
+            Ok(contents) => String::from_utf8_lossy(&contents).into_owned(),
+            Err(e) => {
+                eprintln!("Warning: {}. Skipping it.", e);
+                continue;
             }
+        };
+        for (i, line) in contents.lines().enumerate() {
+            if regex.is_match(line) {
+                println!("{}:{}: {}", file.relative_path, i + 1, line);
+                found = true;
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn handle_tree(state: &State, max_depth: Option<usize>) {
+    if state.files.is_empty() {
+        println!("No files have been added yet.");
+    } else {
+        let paths = state.files.iter().map(|file| file.relative_path.as_str());
+        print!("{}", tree::render(paths, max_depth));
+    }
+}
+
+fn handle_open(state: &State, pattern: Option<String>) -> Result<(), AppError> {
+    if state.files.is_empty() {
+        println!("No files have been added yet.");
+        return Ok(());
+    }
+    let patterns: Vec<_> = pattern.into_iter().collect();
+    let mut files = select_files(&state.files, &patterns)?;
+    files.retain(|file| {
+        let is_local = file.local_path().is_some();
+        if !is_local {
+            eprintln!(
+                "Warning: {} is a remote file, not opening it.",
+                file.relative_path
+            );
+        }
+        is_local
+    });
+    if files.is_empty() {
+        return Ok(());
+    }
+    let editor = editor_command()?;
+    // The variables may contain arguments too, e.g. `code --wait`.
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let status = std::process::Command::new(program)
+        .args(words)
+        .args(files.iter().filter_map(|file| file.local_path()))
+        .status()
+        .map_err(|e| AppError::CustomError(format!("Failed to run '{}': {}", editor, e)))?;
+    if !status.success() {
+        return Err(AppError::CustomError(format!(
+            "'{}' exited with {}",
+            editor, status
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the editor from `$VISUAL` or `$EDITOR`, or a default one for the platform.
+fn editor_command() -> Result<String, AppError> {
+    for variable in ["VISUAL", "EDITOR"] {
+        if let Some(editor) = std::env::var(variable)
+            .ok()
+            .filter(|editor| !editor.trim().is_empty())
+        {
+            return Ok(editor);
         }
     }
-    if added_count > 0 {
+    let defaults: &[&str] = if cfg!(windows) {
+        &["notepad"]
+    } else {
+        &["nano", "vim", "vi"]
+    };
+    defaults
+        .iter()
+        .find(|editor| is_on_path(editor))
+        .map(|editor| editor.to_string())
+        .ok_or_else(|| {
+            AppError::CustomError(
+                "No editor found. Set the VISUAL or EDITOR environment variable".into(),
+            )
+        })
+}
+
+fn is_on_path(program: &str) -> bool {
+    let program = if cfg!(windows) {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(&program).is_file()))
+}
+
+fn handle_clear(state: &mut State, yes: bool, tag: Option<&str>) -> Result<(), AppError> {
+    if let Some(tag) = tag {
+        let count = state.files.iter().filter(|f| f.has_tag(Some(tag))).count();
+        if count == 0 {
+            println!("No files are tagged '{}'.", tag);
+            return Ok(());
+        }
+        let prompt = format!("Remove {} files tagged '{}' from state?", count, tag);
+        if !yes && !confirm(&prompt)? {
+            println!("Aborted.");
+            return Ok(());
+        }
+        state.backup();
+        state.files.retain(|f| !f.has_tag(Some(tag)));
         state.save()?;
-        println!("{} file(s) added successfully.", added_count);
+        println!("{} file(s) tagged '{}' removed.", count, tag);
+        return Ok(());
+    }
+    if !yes && !state.files.is_empty() {
+        let prompt = format!("Clear {} files from state?", state.files.len());
+        if !confirm(&prompt)? {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+    // Clearing an empty state again mustn't replace the backup of the real clear.
+    if !state.files.is_empty() || !state.sources.is_empty() {
+        state.backup();
+    }
+    state.files.clear();
+    state.sources.clear();
+    state.save()?;
+    println!("State cleared.");
+    Ok(())
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no. Fails instead of
+/// waiting for an answer that will never come when stdin isn't interactive.
+fn confirm(prompt: &str) -> Result<bool, AppError> {
+    if !std::io::stdin().is_terminal() {
+        return Err(AppError::CustomError(format!(
+            "{} Refusing to continue without confirmation, pass --yes to skip it",
+            prompt
+        )));
+    }
+    eprint!("{} [y/N] ", prompt);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Removes the entries whose files no longer exist and returns them.
+fn prune_missing(state: &mut State) -> Vec<FileEntry> {
+    let (kept, missing): (Vec<_>, Vec<_>) = state.files.drain(..).partition(FileEntry::exists);
+    state.files = kept;
+    missing
+}
+
+fn handle_prune(state: &mut State, report: ReportArgs) -> Result<(), AppError> {
+    let missing = prune_missing(state);
+    if !missing.is_empty() {
+        state.backup();
+        state.save()?;
+    }
+    report.json(serde_json::json!({
+        "pruned": missing.len(),
+        "total": state.files.len(),
+    }));
+    if !report.messages() {
+        return Ok(());
+    }
+    if missing.is_empty() {
+        println!("No missing files to prune.");
     } else {
-        println!("No new files added.")
+        for file in &missing {
+            println!("- {}", file.relative_path);
+        }
+        println!("{} missing file(s) pruned.", missing.len());
     }
     Ok(())
 }
 
-fn handle_list(state: &State, long: bool) {
+/// Prints a report of the problems with every file. Returns whether all files are fine.
+fn handle_validate(state: &State) -> Result<bool, AppError> {
     if state.files.is_empty() {
         println!("No files have been added yet.");
+        return Ok(true);
+    }
+    let root = project_root(&std::env::current_dir()?);
+    let problems: Vec<_> = state
+        .files
+        .iter()
+        .map(|file| (file, validate_file(file, root.as_deref())))
+        .collect();
+    let width = column_width(problems.iter().map(|(file, _)| &file.relative_path));
+    let mut failed = 0;
+    for (file, problem) in &problems {
+        match problem {
+            None => println!("OK       {}", file.relative_path),
+            Some(problem) => {
+                println!("PROBLEM  {:<width$}  {}", file.relative_path, problem);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        println!("\n{} of {} file(s) have problems.", failed, problems.len());
     } else {
-        println!("Files in state:");
-        for file in &state.files {
-            if long {
-                println!(
-                    "- {} ({})",
-                    file.relative_path,
-                    file.absolute_path.to_string_lossy().into_owned()
-                );
+        println!("\nAll {} file(s) are OK.", problems.len());
+    }
+    Ok(failed == 0)
+}
+
+/// Returns what's wrong with a file, if anything.
+fn validate_file(file: &FileEntry, root: Option<&Path>) -> Option<String> {
+    let contents = match file.local_path() {
+        Some(path) if !path.exists() => return Some("does not exist".into()),
+        Some(path) => match fs::read(path) {
+            Ok(contents) => contents,
+            Err(e) => return Some(format!("can't be read: {}", e)),
+        },
+        None => match file.read() {
+            Ok(contents) => contents,
+            Err(e) => return Some(e.to_string()),
+        },
+    };
+    let Ok(contents) = String::from_utf8(contents) else {
+        return Some("is not valid UTF-8".into());
+    };
+    if let Some((start, end)) = file.range {
+        let line_count = contents.lines().count();
+        if end > line_count {
+            return Some(format!(
+                "lines {}-{} are out of range, it only has {} lines",
+                start, end, line_count
+            ));
+        }
+    }
+    // Paths of files inside the project are stored relative to its root.
+    if let Some(path) = file.local_path() {
+        let base = match &file.relative_to {
+            Some(base) => Some(base.as_path()),
+            None => root.filter(|root| path.starts_with(root)),
+        };
+        if let Some(base) = base {
+            let resolved = fs::canonicalize(base.join(&file.relative_path)).ok();
+            if resolved.as_deref() != Some(path) {
+                return Some(format!(
+                    "the relative path doesn't resolve to {} from {}",
+                    path.display(),
+                    base.display()
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// Prints a single number and nothing else. Unreadable files are left out of the
+/// token and byte totals.
+fn handle_count(state: &State, tokens: bool, bytes: bool) {
+    let count: usize = if tokens {
+        state
+            .files
+            .par_iter()
+            .filter_map(|file| file.read().ok())
+            .map(|contents| tokens::estimate_tokens(&String::from_utf8_lossy(&contents)))
+            .sum()
+    } else if bytes {
+        state
+            .files
+            .iter()
+            .filter_map(|file| match file.local_path() {
+                Some(path) => fs::metadata(path)
+                    .ok()
+                    .map(|metadata| metadata.len() as usize),
+                None => file.read().ok().map(|contents| contents.len()),
+            })
+            .sum()
+    } else {
+        state.files.len()
+    };
+    println!("{}", count);
+}
+
+fn handle_stats(state: &State) {
+    let stats = stats::collection_stats(state);
+    println!("Files: {}", format_thousands(stats.files));
+    println!("Total size: {} bytes", format_thousands(stats.total_bytes));
+    println!("Total lines: {}", format_thousands(stats.total_lines));
+    if stats.unreadable > 0 {
+        println!("Unreadable: {}", format_thousands(stats.unreadable));
+    }
+    if !stats.bytes_by_extension.is_empty() {
+        let width = stats
+            .bytes_by_extension
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        println!();
+        println!("Bytes by extension:");
+        for (extension, bytes) in &stats.bytes_by_extension {
+            println!("  {:<width$}  {:>12}", extension, format_thousands(*bytes));
+        }
+    }
+}
+
+fn handle_merge(state: &mut State, profile: &str) -> Result<(), AppError> {
+    let (other_path, _) = profile_state_path(Some(profile))?;
+    if !other_path.exists() {
+        return Err(AppError::CustomError(format!(
+            "Profile '{}' does not exist",
+            profile
+        )));
+    }
+    let other = State::new(other_path)?;
+    let mut existing_sources: std::collections::HashSet<_> =
+        state.files.iter().map(|f| f.source.clone()).collect();
+    let mut added_count = 0;
+    let mut duplicate_count = 0;
+    for entry in other.files {
+        if existing_sources.insert(entry.source.clone()) {
+            state.push_file(entry);
+            added_count += 1;
+        } else {
+            duplicate_count += 1;
+        }
+    }
+    for source in other.sources {
+        if !state.sources.iter().any(|s| s.path == source.path) {
+            state.sources.push(source);
+        }
+    }
+    for exclude in other.default_excludes {
+        if !state.default_excludes.contains(&exclude) {
+            state.default_excludes.push(exclude);
+        }
+    }
+    state.save()?;
+    println!(
+        "{} file(s) merged from profile '{}', {} skipped as duplicates.",
+        added_count, profile, duplicate_count
+    );
+    Ok(())
+}
+
+fn handle_copy(state: &State, to: &str, force: bool) -> Result<(), AppError> {
+    let path = if to.contains('/') || to.ends_with(".json") {
+        PathBuf::from(to)
+    } else {
+        profile_state_path(Some(to))?.0
+    };
+    let same_file = match (fs::canonicalize(&path), fs::canonicalize(&state.path)) {
+        (Ok(path), Ok(state_path)) => path == state_path,
+        _ => path == state.path,
+    };
+    if same_file {
+        return Err(AppError::CustomError(
+            "Can't copy the state onto itself".into(),
+        ));
+    }
+    if path.exists() && !force {
+        return Err(AppError::CustomError(format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        )));
+    }
+    state.save_to(&path)?;
+    println!(
+        "Copied {} file(s) to {}.",
+        state.files.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn handle_excludes(state: &mut State, command: ExcludesCommand) -> Result<(), AppError> {
+    match command {
+        ExcludesCommand::Add { globs } => {
+            let mut added = 0;
+            for glob in globs {
+                // Checked here, so that a typo doesn't break every later `add`.
+                globset::Glob::new(&glob)?;
+                if state.default_excludes.contains(&glob) {
+                    println!("'{}' is already excluded.", glob);
+                } else {
+                    state.default_excludes.push(glob);
+                    added += 1;
+                }
+            }
+            if added > 0 {
+                state.save()?;
+                println!("{} exclude pattern(s) added.", added);
+            }
+        }
+        ExcludesCommand::List => {
+            if state.default_excludes.is_empty() {
+                println!("No default excludes.");
+            }
+            for glob in &state.default_excludes {
+                println!("{}", glob);
+            }
+        }
+        ExcludesCommand::Remove { globs } => {
+            let before = state.default_excludes.len();
+            for glob in &globs {
+                if !state.default_excludes.contains(glob) {
+                    eprintln!("Warning: '{}' is not a default exclude.", glob);
+                }
+            }
+            state
+                .default_excludes
+                .retain(|exclude| !globs.contains(exclude));
+            let removed = before - state.default_excludes.len();
+            if removed > 0 {
+                state.save()?;
+                println!("{} exclude pattern(s) removed.", removed);
             } else {
-                println!("- {}", file.relative_path)
+                println!("No exclude patterns removed.");
             }
         }
     }
+    Ok(())
 }
 
-fn handle_clear(state: &mut State) -> Result<(), AppError> {
-    state.files.clear();
+fn handle_restore(state: &State) -> Result<(), AppError> {
+    let backup_path = state.backup_path();
+    if !backup_path.exists() {
+        return Err(AppError::CustomError(format!(
+            "No backup found at {}",
+            backup_path.display()
+        )));
+    }
+    // Swap rather than overwrite, so running `restore` again redoes the change.
+    if state.path.exists() {
+        let mut swap_path = state.path.clone().into_os_string();
+        swap_path.push(".swap");
+        fs::rename(&state.path, &swap_path)?;
+        fs::rename(&backup_path, &state.path)?;
+        fs::rename(&swap_path, &backup_path)?;
+    } else {
+        fs::rename(&backup_path, &state.path)?;
+    }
+    println!("State restored from {}.", backup_path.display());
+    Ok(())
+}
+
+fn handle_export(state: &State, out: &Path) -> Result<(), AppError> {
+    let cwd = std::env::current_dir()?;
+    let root = project_root(&cwd).unwrap_or(cwd);
+    // `--relative-to` directories are stored relative to the project root, so they
+    // resolve in any clone of it. The added sources are absolute paths on this
+    // machine and aren't exported.
+    let files = state
+        .files
+        .iter()
+        .cloned()
+        .map(|mut file| {
+            if let Some(relative) = file
+                .relative_to
+                .as_deref()
+                .and_then(|dir| dir.strip_prefix(&root).ok())
+            {
+                file.relative_to =
+                    (!relative.as_os_str().is_empty()).then(|| relative.to_path_buf());
+            }
+            file
+        })
+        .collect();
+    let exported = State {
+        files,
+        sources: Vec::new(),
+        default_excludes: state.default_excludes.clone(),
+        path: out.to_path_buf(),
+    };
+    exported.save()?;
+    println!(
+        "Exported {} file(s) to {}.",
+        exported.files.len(),
+        out.display()
+    );
+    Ok(())
+}
+
+fn handle_import(state: &mut State, file: &Path, merge: bool) -> Result<(), AppError> {
+    let imported: State = serde_json::from_str(&fs::read_to_string(file)?)?;
+    // Exported relative paths are resolved against this clone of the project.
+    let cwd = std::env::current_dir()?;
+    let base = project_root(&cwd).unwrap_or(cwd);
+
+    if !merge {
+        state.files.clear();
+        state.sources.clear();
+        state.default_excludes.clear();
+    }
+    for exclude in imported.default_excludes {
+        if !state.default_excludes.contains(&exclude) {
+            state.default_excludes.push(exclude);
+        }
+    }
+    let mut existing_sources: std::collections::HashSet<_> =
+        state.files.iter().map(|f| f.source.clone()).collect();
+    let mut imported_count = 0;
+    for entry in imported.files {
+        let (source, relative_to) = match entry.source {
+            Source::Local { .. } => {
+                // Exports store `--relative-to` relative to the project root. Older
+                // ones stored the exporter's absolute path, which most likely
+                // doesn't exist here, so the project root is used instead.
+                let relative_to = entry
+                    .relative_to
+                    .as_deref()
+                    .and_then(|dir| fs::canonicalize(base.join(dir)).ok());
+                let Ok(absolute_path) = fs::canonicalize(
+                    relative_to
+                        .as_deref()
+                        .unwrap_or(&base)
+                        .join(&entry.relative_path),
+                ) else {
+                    eprintln!(
+                        "Warning: {} does not exist here. Skipping it.",
+                        entry.relative_path
+                    );
+                    continue;
+                };
+                (Source::Local { absolute_path }, relative_to)
+            }
+            Source::Remote { .. } => (entry.source.clone(), None),
+        };
+        if existing_sources.insert(source.clone()) {
+            state.push_file(FileEntry {
+                source,
+                relative_to,
+                ..entry
+            });
+            imported_count += 1;
+        }
+    }
     state.save()?;
-    println!("State cleared.");
+    if imported_count > 0 {
+        println!("{} file(s) imported successfully.", imported_count);
+    } else {
+        println!("No new files imported.")
+    }
     Ok(())
 }
 
-fn handle_print(state: &State) -> Result<(), AppError> {
+/// Exit status of `print` when the prompt exceeds `--max-tokens`.
+const EXIT_OVER_BUDGET: u8 = 3;
+
+/// Exit status of `print` when there are no files to print. It isn't 2, which is
+/// what invalid arguments exit with.
+const EXIT_EMPTY: u8 = 4;
+
+fn handle_print(state: &mut State, args: &PrintArgs) -> Result<ExitCode, AppError> {
+    let (code, updates) = print_prompt(state, args)?;
+    // The printed contents become the baseline for the next `--only-changed`.
+    if !updates.is_empty() {
+        for update in updates {
+            if let Some(file) = state.files.iter_mut().find(|f| f.source == update.source) {
+                file.hash = Some(update.hash);
+                file.size = Some(update.size);
+            }
+        }
+        state.save()?;
+    }
+    Ok(code)
+}
+
+/// Prints the prompt and returns the exit code, and the changes to record in the
+/// state when `--only-changed` was given and the prompt was output.
+fn print_prompt(
+    state: &State,
+    args: &PrintArgs,
+) -> Result<(ExitCode, Vec<ContentUpdate>), AppError> {
     if state.files.is_empty() {
-        Err(AppError::CustomError("No files to print!".into()))
-    } else {
-        println!("<files>");
-        for file_entry in &state.files {
-            let contents = fs::read_to_string(&file_entry.absolute_path)?;
-            println!("<file path=\"{}\">", file_entry.relative_path);
-            println!("{}", contents);
-            println!("</file>");
-        }
-        println!("</files>");
-        Ok(())
+        if args.allow_empty {
+            return Ok((ExitCode::SUCCESS, Vec::new()));
+        }
+        eprintln!("No files to print!");
+        return Ok((ExitCode::from(EXIT_EMPTY), Vec::new()));
+    }
+    let options = print_options(args)?;
+    options.validate()?;
+    let mut selected = select_files(&state.files, &args.patterns)?;
+    if let Some(tag) = &args.tag {
+        selected.retain(|file| file.has_tag(Some(tag)));
+        if selected.is_empty() {
+            return Err(AppError::CustomError(format!(
+                "No selected files are tagged '{}'",
+                tag
+            )));
+        }
+    }
+    sort_files(&mut selected, &args.sort, |file| file);
+    let mut updates = Vec::new();
+    if args.only_changed {
+        let changes: Vec<_> = selected
+            .par_iter()
+            .map(|file| print::change_of(file))
+            .collect();
+        let mut changes = changes.into_iter();
+        selected.retain(|_| match changes.next() {
+            Some(Change::Changed(update)) => {
+                updates.push(update);
+                true
+            }
+            Some(Change::Unchanged) => false,
+            // Kept, so that printing reports why it can't be read.
+            Some(Change::Unreadable) | None => true,
+        });
+        if selected.is_empty() {
+            eprintln!("No files changed since they were last printed.");
+            return Ok((ExitCode::SUCCESS, updates));
+        }
+    }
+    if can_stream(args, &options) {
+        return Ok((stream_print(&selected, args, &options)?, updates));
+    }
+    let print::RenderedPrompt {
+        text: mut output,
+        files,
+    } = print::render_prompt(state, &selected, &options)?;
+    if args.redact {
+        let redactions: Vec<_> = files.iter().map(|file| file.redactions).collect();
+        print_redaction_summary(&redactions);
+    }
+    let tokenizer = match &args.model {
+        None => Box::new(tokens::Heuristic),
+        Some(model) => tokens::tokenizer_for_model(model).unwrap_or_else(|| {
+            eprintln!(
+                "Warning: No tokenizer known for model '{}', estimating tokens instead",
+                model
+            );
+            Box::new(tokens::Heuristic)
+        }),
+    };
+    let token_count = tokenizer.count_tokens(&output);
+    let mut stats = stats::TextStats::default();
+    stats.add(&output);
+    let over_budget = args.max_tokens.is_some_and(|max| token_count > max);
+    if args.format == OutputFormat::Chat {
+        output = print::chat_messages(args.system.as_deref(), output)?;
+    }
+    if !(over_budget && args.strict) {
+        if let Some(path) = &args.output {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, &output)?;
+            eprintln!("Wrote prompt to {}", path.display());
+        }
+        if args.clipboard {
+            arboard::Clipboard::new()
+                .and_then(|mut clipboard| clipboard.set_text(output))
+                .map_err(|e| AppError::CustomError(format!("Failed to access clipboard: {}", e)))?;
+            eprintln!("Copied prompt ({} files) to clipboard", files.len());
+        } else if args.output.is_none() {
+            print!("{}", output);
+        }
+        if let Some(path) = &args.manifest {
+            let manifest: Vec<_> = files.iter().map(ManifestEntry::new).collect();
+            write_manifest(path, &manifest)?;
+        }
+    }
+    let approximate = if tokenizer.is_exact() { "" } else { "~" };
+    if !args.no_summary {
+        eprintln!(
+            "{}{} tokens, {} across {} files",
+            approximate,
+            format_thousands(token_count),
+            stats,
+            files.len()
+        );
+    }
+    let by_tokens = || {
+        let mut counts: Vec<_> = files
+            .iter()
+            .map(|file| (tokenizer.count_tokens(&file.prompt.contents), file))
+            .collect();
+        counts.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+        counts
+    };
+    if args.breakdown {
+        let breakdown = by_tokens();
+        let counts: Vec<_> = breakdown
+            .iter()
+            .map(|(count, _)| format!("{}{}", approximate, format_thousands(*count)))
+            .collect();
+        let width = column_width(counts.iter());
+        eprintln!("Tokens per file:");
+        for (count, (tokens, file)) in counts.iter().zip(&breakdown) {
+            // Against the whole prompt, so the wrapping tags make up the rest.
+            let share = *tokens as f64 * 100.0 / token_count.max(1) as f64;
+            eprintln!(
+                "  {:>width$} tokens  {:>5.1}%  {}",
+                count, share, file.entry.relative_path
+            );
+        }
+    }
+    if let Some(max_tokens) = args.max_tokens.filter(|_| over_budget) {
+        eprintln!(
+            "WARNING: The prompt exceeds the budget of {} tokens by {}{} tokens!",
+            format_thousands(max_tokens),
+            approximate,
+            format_thousands(token_count - max_tokens)
+        );
+        let mut contributors = by_tokens();
+        contributors.truncate(5);
+        let counts: Vec<_> = contributors
+            .iter()
+            .map(|(count, _)| format!("{}{}", approximate, format_thousands(*count)))
+            .collect();
+        let width = column_width(counts.iter());
+        eprintln!("Largest files:");
+        for (count, (_, file)) in counts.iter().zip(&contributors) {
+            eprintln!("  {:>width$} tokens  {}", count, file.entry.relative_path);
+        }
+        if args.strict {
+            eprintln!("Nothing was output because of --strict.");
+            updates.clear();
+        }
+        return Ok((ExitCode::from(EXIT_OVER_BUDGET), updates));
+    }
+    Ok((ExitCode::SUCCESS, updates))
+}
+
+/// Whether the prompt can be written to stdout file by file, instead of being
+/// assembled in memory first. That's not possible when it's needed as a whole, e.g.
+/// for the clipboard, exact token counts or the token budget.
+fn can_stream(args: &PrintArgs, options: &PrintOptions) -> bool {
+    options.can_stream()
+        && !args.clipboard
+        && args.output.is_none()
+        && args.model.is_none()
+        && args.max_tokens.is_none()
+        && !args.breakdown
+}
+
+/// Writes the prompt to stdout as the files are read, so only a few files are in
+/// memory at a time.
+fn stream_print(
+    entries: &[&FileEntry],
+    args: &PrintArgs,
+    options: &PrintOptions,
+) -> Result<ExitCode, AppError> {
+    let out = std::io::BufWriter::new(std::io::stdout().lock());
+    let streamed = print::stream_prompt(entries, options, out)?;
+    if let Some(path) = &args.manifest {
+        write_manifest(path, &streamed.manifest)?;
+    }
+    if args.redact {
+        print_redaction_summary(&streamed.redactions);
+    }
+    if !args.no_summary {
+        eprintln!(
+            "~{} tokens, {} across {} files",
+            format_thousands(tokens::estimate_tokens_for_chars(streamed.stats.chars())),
+            streamed.stats,
+            streamed.files
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the total number of redactions, given the number for each file.
+fn print_redaction_summary(redactions: &[usize]) {
+    eprintln!(
+        "Redacted {} likely secret(s) in {} file(s)",
+        redactions.iter().sum::<usize>(),
+        redactions.iter().filter(|&&count| count > 0).count()
+    );
+}
+
+fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> Result<(), AppError> {
+    print::write_manifest(path, entries)?;
+    eprintln!("Wrote manifest to {}", path.display());
+    Ok(())
+}
+
+/// The flags in `args` that shape the prompt. Chat messages hold the files in XML.
+fn print_options(args: &PrintArgs) -> Result<PrintOptions, AppError> {
+    Ok(PrintOptions {
+        load: LoadOptions {
+            strip_comments: args.strip_comments,
+            line_numbers: args.line_numbers,
+            squeeze_blank: args.squeeze_blank,
+            max_lines: args.max_lines,
+            hash: args.manifest.is_some(),
+        },
+        render: RenderOptions {
+            format: match args.format {
+                OutputFormat::Xml | OutputFormat::Chat => Format::Xml,
+                OutputFormat::Markdown => Format::Markdown,
+                OutputFormat::Html => Format::Html,
+            },
+            root_tag: args.root_tag.clone(),
+            file_tag: args.file_tag.clone(),
+            raw: args.raw,
+            group_by_dir: args.group_by_dir,
+            with_paths: args.with_paths_header,
+            with_tree: args.with_tree,
+        },
+        chat: args.format == OutputFormat::Chat,
+        system: args.system.clone(),
+        redact: args.redact,
+        no_wrap: args.no_wrap,
+        separator: args.separator.clone(),
+        detect_language: args.detect_language,
+        prefix: text_option(&args.prefix, &args.prefix_file)?,
+        suffix: text_option(&args.suffix, &args.suffix_file)?,
+        template: args.template.clone(),
+        skip_missing: args.skip_missing,
+    })
+}
+
+/// Returns the given text, or the contents of the given file, if either is set.
+fn text_option(text: &Option<String>, file: &Option<PathBuf>) -> Result<Option<String>, AppError> {
+    match (text, file) {
+        (Some(text), _) => Ok(Some(text.clone())),
+        (None, Some(path)) => Ok(Some(fs::read_to_string(path)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_after_clearing_twice_undoes_the_first_clear() {
+        let dir = std::env::temp_dir().join(format!("pb-clear-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.rs");
+        fs::write(&file, "fn main() {}").unwrap();
+        let path = dir.join("state.json");
+        let mut state = State::new(path.clone()).unwrap();
+        add::add_files(
+            &mut state,
+            &[file.to_string_lossy().into_owned()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        state.save().unwrap();
+
+        handle_clear(&mut state, true, None).unwrap();
+        handle_clear(&mut state, true, None).unwrap();
+        handle_restore(&state).unwrap();
+        let restored = State::new(path).unwrap();
+        let file = fs::canonicalize(file).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(restored.files.len(), 1);
+        assert_eq!(restored.files[0].local_path(), Some(file.as_path()));
+    }
+
+    #[test]
+    fn parse_size_accepts_suffixes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("100k"), Ok(100 * 1024));
+        assert_eq!(parse_size("2m"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size("1g"), Ok(1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn parse_size_is_case_insensitive_and_allows_a_b() {
+        assert_eq!(parse_size("100K"), Ok(100 * 1024));
+        assert_eq!(parse_size("2MB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_size(" 64kb "), Ok(64 * 1024));
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid_sizes() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("k").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("1.5m").is_err());
+        assert!(parse_size("2t").is_err());
+        assert!(parse_size("17179869184g").is_err());
+    }
+
+    #[test]
+    fn parse_time_accepts_durations_before_now() {
+        use std::time::{Duration, SystemTime};
+        let two_days = Duration::from_secs(2 * 24 * 60 * 60);
+        let before = SystemTime::now() - two_days;
+        let parsed = parse_time("2d").unwrap();
+        let after = SystemTime::now() - two_days;
+        assert!(before <= parsed && parsed <= after);
+
+        let parsed = parse_time("1week 2days").unwrap();
+        let age = SystemTime::now().duration_since(parsed).unwrap();
+        assert!(age >= Duration::from_secs(9 * 24 * 60 * 60));
+        assert!(parse_time(" 3h ").is_ok());
+    }
+
+    #[test]
+    fn parse_time_accepts_dates_and_timestamps_in_utc() {
+        let midnight = humantime::parse_rfc3339("2024-05-01T00:00:00Z").unwrap();
+        assert_eq!(parse_time("2024-05-01"), Ok(midnight));
+        let noon = humantime::parse_rfc3339("2024-05-01T12:00:00Z").unwrap();
+        assert_eq!(parse_time("2024-05-01 12:00:00"), Ok(noon));
+        assert_eq!(parse_time("2024-05-01T12:00:00Z"), Ok(noon));
+    }
+
+    #[test]
+    fn parse_time_rejects_invalid_times() {
+        assert!(parse_time("").is_err());
+        assert!(parse_time("yesterday").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time(&format!("{}s", u64::MAX)).is_err());
     }
 }
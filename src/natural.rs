@@ -0,0 +1,110 @@
+use std::cmp::Ordering;
+
+/// Compares strings the way people expect file names to be sorted, with runs of
+/// digits compared by their numeric value, so `file2` sorts before `file10`.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = Chunks(a);
+    let mut b_chunks = Chunks(b);
+    loop {
+        match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_chunk), Some(b_chunk)) => {
+                let ordering = compare_chunks(a_chunk, b_chunk);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+fn compare_chunks(a: &str, b: &str) -> Ordering {
+    let is_number = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+    if is_number(a) && is_number(b) {
+        // Numbers of any length are compared without parsing them: after removing
+        // leading zeros, the longer number is the larger one.
+        let a_digits = a.trim_start_matches('0');
+        let b_digits = b.trim_start_matches('0');
+        a_digits
+            .len()
+            .cmp(&b_digits.len())
+            .then_with(|| a_digits.cmp(b_digits))
+    } else {
+        a.cmp(b)
+    }
+}
+
+/// Splits a string into alternating runs of ASCII digits and other characters.
+struct Chunks<'a>(&'a str);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let first = self.0.chars().next()?;
+        let is_digit = first.is_ascii_digit();
+        let end = self
+            .0
+            .find(|c: char| c.is_ascii_digit() != is_digit)
+            .unwrap_or(self.0.len());
+        let (chunk, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| compare(a, b));
+        names
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(compare("file2", "file10"), Ordering::Less);
+        assert_eq!(compare("file10", "file2"), Ordering::Greater);
+        assert_eq!(
+            sorted(&["file10.rs", "file2.rs", "file1.rs"]),
+            ["file1.rs", "file2.rs", "file10.rs"]
+        );
+    }
+
+    #[test]
+    fn mixed_alphanumeric_names() {
+        assert_eq!(
+            sorted(&["v1.10.0", "v1.2.10", "v1.2.9", "a10b2", "a10b10", "a9b20", "b", "a"]),
+            ["a", "a9b20", "a10b2", "a10b10", "b", "v1.2.9", "v1.2.10", "v1.10.0"]
+        );
+        assert_eq!(
+            sorted(&["src/mod10/a.rs", "src/mod2/b.rs", "src/mod2/a.rs"]),
+            ["src/mod2/a.rs", "src/mod2/b.rs", "src/mod10/a.rs"]
+        );
+    }
+
+    #[test]
+    fn numbers_before_letters() {
+        assert_eq!(compare("1file", "file"), Ordering::Less);
+        assert_eq!(compare("file", "file1"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(compare("file007", "file8"), Ordering::Less);
+        assert_eq!(compare("file007", "file7"), Ordering::Less);
+        assert_eq!(compare("file7", "file7"), Ordering::Equal);
+    }
+
+    #[test]
+    fn long_numbers_do_not_overflow() {
+        assert_eq!(
+            compare("build99999999999999999999", "build100000000000000000000"),
+            Ordering::Less
+        );
+    }
+}
@@ -0,0 +1,546 @@
+//! Assembling a prompt from the files in a state, the way `pb print` does.
+
+use crate::files::hash_bytes;
+use crate::load::{self, LoadOptions, LoadedFile};
+use crate::prompt::{self, Format, RenderOptions};
+use crate::redact::Redactor;
+use crate::stats::TextStats;
+use crate::{comments, language, template, AppError, FileEntry, Source, State};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The settings that shape a printed prompt, matching the flags of `pb print`.
+/// Where the prompt goes and what's reported about it is up to the caller.
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    pub load: LoadOptions,
+    pub render: RenderOptions,
+    /// Wrap the prompt into chat messages, with the files in `render.format`
+    pub chat: bool,
+    /// System message of the chat messages
+    pub system: Option<String>,
+    /// Replace likely secrets in the files
+    pub redact: bool,
+    /// Separate files with a comment line instead of wrapping them
+    pub no_wrap: bool,
+    /// Separator line for `no_wrap`, with `{path}` replaced by the file's path
+    pub separator: Option<String>,
+    /// Name the language most of the files are written in before them
+    pub detect_language: bool,
+    /// Text before the files
+    pub prefix: Option<String>,
+    /// Text after the files
+    pub suffix: Option<String>,
+    /// Template the files are inserted into, instead of `prefix` and `suffix`
+    pub template: Option<PathBuf>,
+    /// Leave out files that can't be read, with a warning, instead of failing
+    pub skip_missing: bool,
+}
+
+impl PrintOptions {
+    /// Checks the combinations of options that can't be rendered.
+    pub fn validate(&self) -> Result<(), AppError> {
+        validate_tag_name(&self.render.root_tag)?;
+        validate_tag_name(&self.render.file_tag)?;
+        if self.render.group_by_dir && self.render.format != Format::Xml {
+            return Err(AppError::CustomError(
+                "--group-by-dir is only supported with --format xml or chat".into(),
+            ));
+        }
+        if self.system.is_some() && !self.chat {
+            return Err(AppError::CustomError(
+                "--system is only supported with --format chat".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the prompt can be written file by file with `stream_prompt`, instead
+    /// of being assembled in memory with `render_prompt`.
+    pub fn can_stream(&self) -> bool {
+        !self.render.group_by_dir && !self.chat && self.template.is_none()
+    }
+}
+
+fn validate_tag_name(name: &str) -> Result<(), AppError> {
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '<' || c == '>') {
+        return Err(AppError::CustomError(format!(
+            "Invalid tag name '{}': it must be non-empty and contain no whitespace or angle brackets",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Reads `entries` in parallel and applies the transformations in `options`.
+/// Warnings are printed in the order of the files. A file that can't be read is
+/// an error, or skipped with a warning if `skip_missing` is set.
+pub fn load_files<'a>(
+    entries: &[&'a FileEntry],
+    options: &LoadOptions,
+    redactor: Option<&Redactor>,
+    skip_missing: bool,
+) -> Result<Vec<LoadedFile<'a>>, AppError> {
+    // Files are read in parallel, but collecting an indexed iterator keeps the
+    // results in the original order. Warnings are printed afterwards, in the same
+    // order.
+    let results: Vec<_> = entries
+        .par_iter()
+        .map(|&entry| load::load_file(entry, options, redactor))
+        .collect();
+    let mut files = Vec::with_capacity(entries.len());
+    for result in results {
+        match result {
+            Ok(file) => {
+                for warning in &file.warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                files.push(file);
+            }
+            Err(e) if skip_missing => eprintln!("Warning: {}. Skipping it.", e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(files)
+}
+
+/// A prompt assembled in memory by `render_prompt`.
+pub struct RenderedPrompt<'a> {
+    /// The prompt, without the chat messages around it
+    pub text: String,
+    /// The files in it, in order
+    pub files: Vec<LoadedFile<'a>>,
+}
+
+/// Reads `entries` and renders them into a prompt. Templates can refer to any
+/// file in `state`.
+pub fn render_prompt<'a>(
+    state: &State,
+    entries: &[&'a FileEntry],
+    options: &PrintOptions,
+) -> Result<RenderedPrompt<'a>, AppError> {
+    let redactor = options.redact.then(Redactor::new);
+    let files = load_files(
+        entries,
+        &options.load,
+        redactor.as_ref(),
+        options.skip_missing,
+    )?;
+    if files.is_empty() {
+        return Err(AppError::CustomError(
+            "None of the files could be read!".into(),
+        ));
+    }
+    let mut block = header(entries, options);
+    if options.no_wrap {
+        for file in &files {
+            push_file(&mut block, file, options);
+        }
+    } else {
+        let prompt_files: Vec<_> = files.iter().map(|file| &file.prompt).collect();
+        block.push_str(&prompt::render(&prompt_files, &options.render));
+    }
+    let text = match &options.template {
+        Some(path) => expand_template(path, state, &block, options, redactor.as_ref())?,
+        None => {
+            let mut text = String::new();
+            if let Some(prefix) = &options.prefix {
+                push_line(&mut text, prefix);
+            }
+            text.push_str(&block);
+            if let Some(suffix) = &options.suffix {
+                push_line(&mut text, suffix);
+            }
+            text
+        }
+    };
+    Ok(RenderedPrompt { text, files })
+}
+
+/// What `stream_prompt` wrote.
+pub struct StreamedPrompt {
+    /// Number of files written
+    pub files: usize,
+    /// Number of secrets replaced in each file
+    pub redactions: Vec<usize>,
+    pub manifest: Vec<ManifestEntry>,
+    pub stats: TextStats,
+}
+
+/// Number of files read (in parallel) at a time when streaming the prompt.
+const STREAM_CHUNK_SIZE: usize = 64;
+
+/// Writes the prompt to `out` as the files are read, so only a few files are in
+/// memory at a time. The options must allow streaming, see
+/// `PrintOptions::can_stream`.
+pub fn stream_prompt(
+    entries: &[&FileEntry],
+    options: &PrintOptions,
+    out: impl Write,
+) -> Result<StreamedPrompt, AppError> {
+    let mut out = CountingWriter::new(out);
+    let mut streamed = StreamedPrompt {
+        files: 0,
+        redactions: Vec::new(),
+        manifest: Vec::new(),
+        stats: TextStats::default(),
+    };
+    let wraps_xml = !options.no_wrap && options.render.format == Format::Xml;
+    let redactor = options.redact.then(Redactor::new);
+    for chunk in entries.chunks(STREAM_CHUNK_SIZE) {
+        let files = load_files(
+            chunk,
+            &options.load,
+            redactor.as_ref(),
+            options.skip_missing,
+        )?;
+        for file in files {
+            // Nothing is written until a file could be read, so that failing on the
+            // first file doesn't leave half a prompt behind.
+            let mut output = String::new();
+            if streamed.files == 0 {
+                if let Some(prefix) = &options.prefix {
+                    push_line(&mut output, prefix);
+                }
+                output.push_str(&header(entries, options));
+                if wraps_xml {
+                    output.push_str(&format!("<{}>\n", options.render.root_tag));
+                }
+            }
+            if !options.no_wrap && options.render.format == Format::Markdown && streamed.files > 0 {
+                output.push('\n');
+            }
+            push_file(&mut output, &file, options);
+            out.write_str(&output)?;
+            streamed.redactions.push(file.redactions);
+            streamed.manifest.push(ManifestEntry::new(&file));
+            streamed.files += 1;
+        }
+    }
+    if streamed.files == 0 {
+        return Err(AppError::CustomError(
+            "None of the files could be read!".into(),
+        ));
+    }
+    let mut end = String::new();
+    if wraps_xml {
+        end.push_str(&format!("</{}>\n", options.render.root_tag));
+    }
+    if let Some(suffix) = &options.suffix {
+        push_line(&mut end, suffix);
+    }
+    out.write_str(&end)?;
+    out.inner.flush()?;
+    streamed.stats = out.stats;
+    Ok(streamed)
+}
+
+/// The language hint, the list of paths and the directory tree before the files,
+/// as requested in `options`.
+fn header(entries: &[&FileEntry], options: &PrintOptions) -> String {
+    let mut header = String::new();
+    if options.detect_language {
+        header.push_str(&language_hint(entries, options));
+    }
+    if options.render.with_paths {
+        header.push_str(&prompt::render_paths(
+            entries.iter().map(|file| file.display_path()),
+            &options.render,
+        ));
+    }
+    if options.render.with_tree {
+        header.push_str(&prompt::render_tree(
+            entries.iter().map(|file| file.display_path()),
+            &options.render,
+        ));
+    }
+    header
+}
+
+/// Counts what's written through it, to estimate tokens without keeping the whole
+/// prompt around.
+struct CountingWriter<W> {
+    inner: W,
+    stats: TextStats,
+}
+
+impl<W: Write> CountingWriter<W> {
+    fn new(inner: W) -> Self {
+        CountingWriter {
+            inner,
+            stats: TextStats::default(),
+        }
+    }
+
+    fn write_str(&mut self, s: &str) -> std::io::Result<()> {
+        self.stats.add(s);
+        self.inner.write_all(s.as_bytes())
+    }
+}
+
+/// Expands the placeholders in the template at `path`: `{{files}}` becomes `block`,
+/// the rendered files, and `{{file:PATH}}` the file in the state with that path.
+fn expand_template(
+    path: &Path,
+    state: &State,
+    block: &str,
+    options: &PrintOptions,
+    redactor: Option<&Redactor>,
+) -> Result<String, AppError> {
+    let template = fs::read_to_string(path).map_err(|source| AppError::FileReadError {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let invalid =
+        |e: String| AppError::CustomError(format!("Invalid template {}: {}", path.display(), e));
+    let mut output = String::new();
+    for part in template::parse(&template).map_err(invalid)? {
+        match part {
+            template::Part::Text(text) => output.push_str(text),
+            template::Part::Files => output.push_str(block),
+            template::Part::File {
+                path: file_path,
+                location,
+            } => {
+                let entry = state
+                    .files
+                    .iter()
+                    .find(|file| {
+                        file.relative_path == file_path
+                            || file.display_name.as_deref() == Some(file_path)
+                    })
+                    .ok_or_else(|| {
+                        invalid(format!(
+                            "{} (in {{{{file:{}}}}} at {}) is not in the state",
+                            file_path, file_path, location
+                        ))
+                    })?;
+                push_file(
+                    &mut output,
+                    &load::load_file(entry, &options.load, redactor)?,
+                    options,
+                );
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: String,
+}
+
+/// Wraps the prompt into a JSON array of OpenAI-style chat messages: the system
+/// message, if any, followed by the prompt as the user message.
+pub fn chat_messages(system: Option<&str>, prompt: String) -> Result<String, AppError> {
+    let mut messages = Vec::new();
+    if let Some(system) = system {
+        messages.push(ChatMessage {
+            role: "system",
+            content: system.to_string(),
+        });
+    }
+    messages.push(ChatMessage {
+        role: "user",
+        content: prompt,
+    });
+    let mut json = serde_json::to_string_pretty(&messages)?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// A file in the `--manifest` of a printed prompt.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    pub relative_path: String,
+    pub size: u64,
+    pub hash: String,
+}
+
+impl ManifestEntry {
+    /// The entry for a file loaded with `LoadOptions::hash` set.
+    pub fn new(file: &LoadedFile) -> Self {
+        ManifestEntry {
+            relative_path: file.entry.relative_path.clone(),
+            size: file.size,
+            hash: file.hash.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Writes `entries` to `path` as a JSON array.
+pub fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> Result<(), AppError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(entries)? + "\n")?;
+    Ok(())
+}
+
+/// Whether a file changed since it was last printed, see `change_of`.
+pub enum Change {
+    Unchanged,
+    Changed(ContentUpdate),
+    Unreadable,
+}
+
+/// The current contents of a file that changed, to record in the state after
+/// printing it.
+pub struct ContentUpdate {
+    pub source: Source,
+    pub hash: String,
+    pub size: u64,
+}
+
+/// Compares a file's current contents to the hash stored in the state. Files
+/// without a stored hash count as changed.
+pub fn change_of(file: &FileEntry) -> Change {
+    let Ok(contents) = file.read() else {
+        return Change::Unreadable;
+    };
+    let hash = hash_bytes(&contents);
+    if file.hash.as_ref() == Some(&hash) {
+        Change::Unchanged
+    } else {
+        Change::Changed(ContentUpdate {
+            source: file.source.clone(),
+            hash,
+            size: contents.len() as u64,
+        })
+    }
+}
+
+/// Appends `text` to `output`, terminating it with a newline if it lacks one.
+fn push_line(output: &mut String, text: &str) {
+    output.push_str(text);
+    if !text.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+/// Renders a single file in the format given in `options`, or after a separator
+/// line with `no_wrap`.
+fn push_file(output: &mut String, file: &LoadedFile, options: &PrintOptions) {
+    if !options.no_wrap {
+        prompt::push_file(output, &file.prompt, &options.render);
+        return;
+    }
+    let path = &file.prompt.path;
+    let separator = match &options.separator {
+        Some(separator) => separator.replace("{path}", path),
+        None => comments::comment_line(file.prompt.language, path),
+    };
+    push_line(output, &separator);
+    if !file.prompt.contents.is_empty() {
+        push_line(output, &file.prompt.contents);
+    }
+}
+
+/// Languages of files that describe or configure a project rather than implement it.
+const NON_CODE_LANGUAGES: &[&str] = &["json", "markdown", "text", "toml", "xml", "yaml"];
+
+/// Returns the language most of `entries` are written in, ignoring documentation and
+/// data files. Ties go to the language whose first file comes first.
+pub fn primary_language(entries: &[&FileEntry]) -> Option<&'static str> {
+    let mut counts: Vec<(&'static str, usize)> = Vec::new();
+    for entry in entries {
+        let Some(language) = language::language_for_path(Path::new(&entry.relative_path)) else {
+            continue;
+        };
+        if NON_CODE_LANGUAGES.contains(&language) {
+            continue;
+        }
+        match counts
+            .iter_mut()
+            .find(|(existing, _)| *existing == language)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((language, 1)),
+        }
+    }
+    // `max_by_key` returns the last maximum, so the counts are searched in reverse.
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|&(_, count)| count)
+        .map(|(language, _)| language)
+}
+
+/// The `detect_language` hint in the format given in `options`, or nothing if none
+/// of the files is code.
+fn language_hint(entries: &[&FileEntry], options: &PrintOptions) -> String {
+    let Some(language) = primary_language(entries) else {
+        return String::new();
+    };
+    match options.render.format {
+        _ if options.no_wrap => format!("Primary language: {}\n", language),
+        Format::Xml => format!("<meta language=\"{}\" />\n", language),
+        Format::Markdown => format!("Primary language: {}\n\n", language),
+        Format::Html => format!("<meta name=\"language\" content=\"{}\">\n", language),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_files_matches_loading_sequentially() {
+        let dir = std::env::temp_dir().join(format!("pb-load-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let entries: Vec<FileEntry> = (0..100)
+            .map(|i| {
+                let relative_path = format!("file{}.rs", i);
+                let contents = match i % 4 {
+                    0 => format!("// file {}\nfn f{}() {{}}\n\n\n\n", i, i).into_bytes(),
+                    1 => format!("\u{FEFF}let token = \"ghp_{}\";\n", "a".repeat(36)).into_bytes(),
+                    2 => vec![b'x', 0xff, b'\n'],
+                    _ => "line\n".repeat(i).into_bytes(),
+                };
+                let absolute_path = dir.join(&relative_path);
+                fs::write(&absolute_path, contents).unwrap();
+                FileEntry {
+                    relative_path,
+                    source: Source::Local { absolute_path },
+                    hash: None,
+                    size: None,
+                    relative_to: None,
+                    range: None,
+                    tags: Vec::new(),
+                    display_name: None,
+                    added_at: None,
+                    order: i as u64,
+                }
+            })
+            .collect();
+        let entries: Vec<&FileEntry> = entries.iter().collect();
+        let options = LoadOptions {
+            strip_comments: true,
+            line_numbers: true,
+            squeeze_blank: true,
+            max_lines: Some(20),
+            hash: true,
+        };
+        let redactor = Redactor::new();
+
+        let parallel = load_files(&entries, &options, Some(&redactor), false).unwrap();
+        let sequential: Vec<_> = entries
+            .iter()
+            .map(|entry| load::load_file(entry, &options, Some(&redactor)).unwrap())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(parallel, sequential);
+        assert_eq!(
+            parallel
+                .iter()
+                .filter(|file| !file.warnings.is_empty())
+                .count(),
+            25
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use crate::add::{self, AddOptions};
+use crate::load::{self, LoadOptions};
+use crate::{escape, language, tree, AppError, LineRange, State};
+use std::borrow::Cow;
+use std::path::Path;
+
+/// How files are laid out in a rendered prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// A `<file>` tag per file, inside a root tag
+    #[default]
+    Xml,
+    /// A heading and a fenced code block per file
+    Markdown,
+    /// A collapsible `<details>` element per file, for embedding in a web page
+    Html,
+}
+
+/// Options for rendering a prompt, matching the flags of `pb print`.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub format: Format,
+    /// Name of the root tag wrapping all files in XML output
+    pub root_tag: String,
+    /// Name of the tag wrapping each file in XML output
+    pub file_tag: String,
+    /// Don't XML-escape file contents and paths
+    pub raw: bool,
+    /// Wrap files in a `<directory>` tag per top-level directory in XML output
+    pub group_by_dir: bool,
+    /// List the paths of the files before their contents
+    pub with_paths: bool,
+    /// Show a directory tree of the files before their contents
+    pub with_tree: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            format: Format::Xml,
+            root_tag: "files".to_string(),
+            file_tag: "file".to_string(),
+            raw: false,
+            group_by_dir: false,
+            with_paths: false,
+            with_tree: false,
+        }
+    }
+}
+
+/// A file as it appears in a prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptFile {
+    /// Path shown for the file, usually relative to the project root
+    pub path: String,
+    pub contents: String,
+    /// Lines of the file that `contents` holds, if it's only a part of it
+    pub lines: Option<LineRange>,
+    /// Whether `contents` was cut short
+    pub truncated: bool,
+    /// Language of the code block in Markdown and HTML output
+    pub language: Option<&'static str>,
+}
+
+impl PromptFile {
+    /// A whole file, with the language guessed from its path.
+    pub fn new(path: impl Into<String>, contents: impl Into<String>) -> Self {
+        let path = path.into();
+        PromptFile {
+            language: language::language_for_path(Path::new(&path)),
+            path,
+            contents: contents.into(),
+            lines: None,
+            truncated: false,
+        }
+    }
+}
+
+/// The files of a prompt, in the order they are rendered.
+#[derive(Debug, Clone, Default)]
+pub struct Prompt {
+    pub files: Vec<PromptFile>,
+}
+
+impl Prompt {
+    /// Reads the files at `paths` the way `pb add` and `pb print` do by default:
+    /// directories are walked recursively, `.gitignore` and `.promptignore` are
+    /// respected, and hidden, `*.lock`, binary and duplicate files are skipped. Files
+    /// inside the project get paths relative to its root, invalid UTF-8 is replaced
+    /// with U+FFFD and a leading byte order mark is removed.
+    pub fn from_paths<P: AsRef<Path>>(
+        paths: impl IntoIterator<Item = P>,
+    ) -> Result<Self, AppError> {
+        let patterns: Vec<String> = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_string_lossy().into_owned())
+            .collect();
+        let mut state = State::default();
+        add::add_files(&mut state, &patterns, &AddOptions::default())?;
+        let files = state
+            .files
+            .iter()
+            .map(|entry| {
+                let file = load::load_file(entry, &LoadOptions::default(), None)?;
+                for warning in &file.warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                Ok(file.prompt)
+            })
+            .collect::<Result<_, AppError>>()?;
+        Ok(Prompt { files })
+    }
+
+    /// Renders the prompt, with the list of paths and the directory tree of its
+    /// files first if `options.with_paths` and `options.with_tree` are set.
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let files: Vec<_> = self.files.iter().collect();
+        let mut output = String::new();
+        if options.with_paths {
+            output.push_str(&render_paths(
+                files.iter().map(|file| file.path.as_str()),
+                options,
+            ));
+        }
+        if options.with_tree {
+            output.push_str(&render_tree(
+                files.iter().map(|file| file.path.as_str()),
+                options,
+            ));
+        }
+        output.push_str(&render(&files, options));
+        output
+    }
+}
+
+/// Renders `files` in the format given in `options`, without a directory tree.
+pub fn render(files: &[&PromptFile], options: &RenderOptions) -> String {
+    match options.format {
+        Format::Xml => render_xml(files, options),
+        Format::Markdown => render_markdown(files),
+        Format::Html => render_html(files),
+    }
+}
+
+/// Renders a single file in the format given in `options`.
+pub fn push_file(output: &mut String, file: &PromptFile, options: &RenderOptions) {
+    match options.format {
+        Format::Xml => push_xml_file(output, file, options),
+        Format::Markdown => push_markdown_file(output, file),
+        Format::Html => push_html_file(output, file),
+    }
+}
+
+/// Renders the directory tree of `paths` in a `<tree>` tag or a code block
+/// depending on the format.
+pub fn render_tree<'a>(
+    paths: impl IntoIterator<Item = &'a str>,
+    options: &RenderOptions,
+) -> String {
+    let tree = tree::render(paths, None);
+    match options.format {
+        Format::Xml => {
+            let tree = if options.raw {
+                tree.as_str().into()
+            } else {
+                escape::xml_text(&tree)
+            };
+            format!("<tree>\n{}</tree>\n", tree)
+        }
+        Format::Markdown => format!("```\n{}```\n\n", tree),
+        Format::Html => format!("<pre class=\"tree\">{}</pre>\n", escape::html(&tree)),
+    }
+}
+
+/// Renders `paths` one per line, in a `<paths>` tag or a list depending on the format.
+pub fn render_paths<'a>(
+    paths: impl IntoIterator<Item = &'a str>,
+    options: &RenderOptions,
+) -> String {
+    let paths = paths.into_iter();
+    match options.format {
+        Format::Xml => {
+            let mut output = String::from("<paths>\n");
+            for path in paths {
+                output.push_str(&if options.raw {
+                    path.into()
+                } else {
+                    escape::xml_text(path)
+                });
+                output.push('\n');
+            }
+            output.push_str("</paths>\n");
+            output
+        }
+        Format::Markdown => {
+            let mut output: String = paths.map(|path| format!("- `{}`\n", path)).collect();
+            output.push('\n');
+            output
+        }
+        Format::Html => {
+            let mut output = String::from("<ul class=\"paths\">\n");
+            for path in paths {
+                output.push_str(&format!("<li>{}</li>\n", escape::html(path)));
+            }
+            output.push_str("</ul>\n");
+            output
+        }
+    }
+}
+
+fn render_xml(files: &[&PromptFile], options: &RenderOptions) -> String {
+    let mut output = format!("<{}>\n", options.root_tag);
+    if options.group_by_dir {
+        for (directory, files) in group_by_dir(files) {
+            match directory {
+                Some(directory) => {
+                    output.push_str(&format!(
+                        "<directory path=\"{}\">\n",
+                        xml_attr(directory, options)
+                    ));
+                    for file in files {
+                        push_xml_file(&mut output, file, options);
+                    }
+                    output.push_str("</directory>\n");
+                }
+                None => push_xml_file(&mut output, files[0], options),
+            }
+        }
+    } else {
+        for file in files {
+            push_xml_file(&mut output, file, options);
+        }
+    }
+    output.push_str(&format!("</{}>\n", options.root_tag));
+    output
+}
+
+fn push_xml_file(output: &mut String, file: &PromptFile, options: &RenderOptions) {
+    let contents = if options.raw {
+        file.contents.as_str().into()
+    } else {
+        escape::xml_text(&file.contents)
+    };
+    let mut attributes = String::new();
+    if let Some((start, end)) = file.lines {
+        attributes.push_str(&format!(" lines=\"{}-{}\"", start, end));
+    }
+    if file.truncated {
+        attributes.push_str(" truncated=\"true\"");
+    }
+    output.push_str(&format!(
+        "<{} path=\"{}\"{}>\n",
+        options.file_tag,
+        xml_attr(&file.path, options),
+        attributes
+    ));
+    output.push_str(&contents);
+    output.push_str(&format!("\n</{}>\n", options.file_tag));
+}
+
+fn xml_attr<'a>(value: &'a str, options: &RenderOptions) -> Cow<'a, str> {
+    if options.raw {
+        value.into()
+    } else {
+        escape::xml_attr(value)
+    }
+}
+
+/// Groups files by the first component of their path, in the order each directory
+/// first appears. Files at the root form a group of their own, without a directory.
+fn group_by_dir<'a>(files: &[&'a PromptFile]) -> Vec<(Option<&'a str>, Vec<&'a PromptFile>)> {
+    let mut groups: Vec<(Option<&str>, Vec<&PromptFile>)> = Vec::new();
+    for &file in files {
+        let directory = file.path.split_once('/').map(|(directory, _)| directory);
+        let group = directory.and_then(|directory| {
+            groups
+                .iter_mut()
+                .find(|(existing, _)| *existing == Some(directory))
+        });
+        match group {
+            Some((_, group)) => group.push(file),
+            None => groups.push((directory, vec![file])),
+        }
+    }
+    groups
+}
+
+fn render_markdown(files: &[&PromptFile]) -> String {
+    let mut output = String::new();
+    for (i, file) in files.iter().enumerate() {
+        if i > 0 {
+            output.push('\n');
+        }
+        push_markdown_file(&mut output, file);
+    }
+    output
+}
+
+fn push_markdown_file(output: &mut String, file: &PromptFile) {
+    let contents = &file.contents;
+    // The fence has to be longer than any backtick run inside the file.
+    let fence = "`".repeat(longest_backtick_run(contents).max(2) + 1);
+    match file.lines {
+        Some((start, end)) => {
+            output.push_str(&format!("### {} (lines {}-{})\n\n", file.path, start, end))
+        }
+        None => output.push_str(&format!("### {}\n\n", file.path)),
+    }
+    output.push_str(&format!("{}{}\n", fence, file.language.unwrap_or("")));
+    output.push_str(contents);
+    if !contents.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(&format!("{}\n", fence));
+}
+
+fn render_html(files: &[&PromptFile]) -> String {
+    let mut output = String::new();
+    for file in files {
+        push_html_file(&mut output, file);
+    }
+    output
+}
+
+fn push_html_file(output: &mut String, file: &PromptFile) {
+    let path = escape::html(&file.path);
+    let summary = match file.lines {
+        Some((start, end)) => format!("{} (lines {}-{})", path, start, end),
+        None => path.into_owned(),
+    };
+    // The class is the convention highlight.js and Prism use to pick a language.
+    let class = file
+        .language
+        .map(|language| format!(" class=\"language-{}\"", language))
+        .unwrap_or_default();
+    output.push_str(&format!(
+        "<details>\n<summary>{}</summary>\n<pre><code{}>{}</code></pre>\n</details>\n",
+        summary,
+        class,
+        escape::html(&file.contents)
+    ));
+}
+
+fn longest_backtick_run(s: &str) -> usize {
+    s.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<PromptFile> {
+        vec![
+            PromptFile::new("src/main.rs", "fn main() {}"),
+            PromptFile::new("README.md", "# Readme </file>"),
+            PromptFile::new("tests/it.rs", "#[test] fn it() {}"),
+            PromptFile::new("src/bin/tool.rs", "fn main() {}"),
+            PromptFile::new("Cargo.toml", "[package]"),
+        ]
+    }
+
+    /// Checks that every tag in `xml` is closed in the right order, which holds for
+    /// well-formed XML because contents and attributes are escaped.
+    fn assert_well_formed(xml: &str) {
+        let mut open = Vec::new();
+        for tag in xml.split('<').skip(1) {
+            let tag = &tag[..tag.find('>').expect("tags are closed")];
+            match tag.strip_prefix('/') {
+                Some(name) => assert_eq!(open.pop(), Some(name), "in {}", xml),
+                None => open.push(tag.split(' ').next().unwrap()),
+            }
+        }
+        assert!(open.is_empty(), "unclosed {:?} in {}", open, xml);
+    }
+
+    #[test]
+    fn group_by_dir_keeps_root_files_on_their_own() {
+        let files = files();
+        let files: Vec<_> = files.iter().collect();
+        let groups: Vec<_> = group_by_dir(&files)
+            .into_iter()
+            .map(|(directory, files)| {
+                let paths: Vec<_> = files.iter().map(|file| file.path.as_str()).collect();
+                (directory, paths)
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (Some("src"), vec!["src/main.rs", "src/bin/tool.rs"]),
+                (None, vec!["README.md"]),
+                (Some("tests"), vec!["tests/it.rs"]),
+                (None, vec!["Cargo.toml"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn group_by_dir_nests_files_in_directory_tags() {
+        let prompt = Prompt { files: files() };
+        let output = prompt.render(&RenderOptions {
+            group_by_dir: true,
+            ..RenderOptions::default()
+        });
+        assert_eq!(
+            output,
+            "<files>\n\
+             <directory path=\"src\">\n\
+             <file path=\"src/main.rs\">\nfn main() {}\n</file>\n\
+             <file path=\"src/bin/tool.rs\">\nfn main() {}\n</file>\n\
+             </directory>\n\
+             <file path=\"README.md\">\n# Readme &lt;/file&gt;\n</file>\n\
+             <directory path=\"tests\">\n\
+             <file path=\"tests/it.rs\">\n#[test] fn it() {}\n</file>\n\
+             </directory>\n\
+             <file path=\"Cargo.toml\">\n[package]\n</file>\n\
+             </files>\n"
+        );
+        assert_well_formed(&output);
+    }
+
+    #[test]
+    fn group_by_dir_escapes_directory_names() {
+        let prompt = Prompt {
+            files: vec![PromptFile::new("a\"<b/c.rs", "c")],
+        };
+        let output = prompt.render(&RenderOptions {
+            group_by_dir: true,
+            ..RenderOptions::default()
+        });
+        assert!(output.contains("<directory path=\"a&quot;&lt;b\">"));
+        assert_well_formed(&output);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::AppError;
+
+/// Time after which a download is given up.
+#[cfg(feature = "remote")]
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Whether an argument names a remote file rather than a path.
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+/// Downloads the document at `url`. Responses other than 2xx are errors.
+#[cfg(feature = "remote")]
+pub fn fetch(url: &str) -> Result<Vec<u8>, AppError> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    let fetch_error = |source| AppError::FetchError {
+        url: url.to_string(),
+        source,
+    };
+    match agent.get(url).call() {
+        Ok(mut response) => response.body_mut().read_to_vec().map_err(fetch_error),
+        Err(ureq::Error::StatusCode(status)) => Err(AppError::HttpStatusError {
+            url: url.to_string(),
+            status,
+        }),
+        Err(source) => Err(fetch_error(source)),
+    }
+}
+
+#[cfg(not(feature = "remote"))]
+pub fn fetch(url: &str) -> Result<Vec<u8>, AppError> {
+    Err(AppError::CustomError(format!(
+        "Can't fetch {}: pb was built without the `remote` feature",
+        url
+    )))
+}
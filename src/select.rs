@@ -0,0 +1,60 @@
+use crate::{AppError, FileEntry};
+use globset::{GlobBuilder, GlobMatcher};
+use std::fs;
+use std::path::PathBuf;
+
+/// Matches state entries against a path or glob given on the command line.
+pub struct EntryMatcher {
+    glob: GlobMatcher,
+    absolute_path: Option<PathBuf>,
+}
+
+impl EntryMatcher {
+    pub fn new(pattern: &str) -> Result<Self, AppError> {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        Ok(EntryMatcher {
+            glob,
+            absolute_path: fs::canonicalize(pattern).ok(),
+        })
+    }
+
+    /// An entry matches if its relative path matches the glob, or if the pattern
+    /// resolves to the entry's file (or to a directory containing it).
+    pub fn is_match(&self, file: &FileEntry) -> bool {
+        self.glob.is_match(&file.relative_path)
+            || self
+                .absolute_path
+                .as_ref()
+                .is_some_and(|path| file.local_path().is_some_and(|p| p.starts_with(path)))
+    }
+}
+
+/// Returns the files matching any of `patterns`, or all files if there are none.
+/// It's an error for a pattern not to match anything, as that's most likely a typo.
+pub fn select_files<'a>(
+    files: &'a [FileEntry],
+    patterns: &[String],
+) -> Result<Vec<&'a FileEntry>, AppError> {
+    if patterns.is_empty() {
+        return Ok(files.iter().collect());
+    }
+    let matchers = patterns
+        .iter()
+        .map(|pattern| EntryMatcher::new(pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (pattern, matcher) in patterns.iter().zip(&matchers) {
+        if !files.iter().any(|file| matcher.is_match(file)) {
+            return Err(AppError::CustomError(format!(
+                "'{}' did not match any files in state",
+                pattern
+            )));
+        }
+    }
+    Ok(files
+        .iter()
+        .filter(|file| matchers.iter().any(|matcher| matcher.is_match(file)))
+        .collect())
+}
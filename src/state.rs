@@ -0,0 +1,222 @@
+use crate::add::AddOptions;
+use crate::{remote, AppError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A file in the collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub relative_path: String,
+    #[serde(flatten)]
+    pub source: Source,
+    /// SHA-256 of the file's contents when it was added, or last printed with
+    /// `--only-changed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Size of the file in bytes, from the same time as `hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Directory `relative_path` is relative to, from `add --relative-to`, if it's
+    /// not the project root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_to: Option<PathBuf>,
+    /// Inclusive, 1-based range of lines to print, from `add path:START-END`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<LineRange>,
+    /// Labels given with `add --tag`, used to print a group of files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Label shown instead of `relative_path` in printed prompts, from `add --name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// When the file was added, in RFC 3339 format. Unknown for files added before
+    /// it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_at: Option<String>,
+    /// Position of the file in the printed output. Files are kept sorted by it, so
+    /// the order doesn't depend on how the state file lists them.
+    #[serde(default)]
+    pub order: u64,
+}
+
+/// A path or glob passed to `add`, with the options it was added with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "SourceRepr")]
+pub struct AddedSource {
+    /// Absolute path or glob
+    pub path: String,
+    #[serde(default, skip_serializing_if = "AddOptions::is_default")]
+    pub options: AddOptions,
+}
+
+/// State files from before the options were stored list just the paths.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SourceRepr {
+    Path(String),
+    Added {
+        path: String,
+        #[serde(default)]
+        options: AddOptions,
+    },
+}
+
+impl From<SourceRepr> for AddedSource {
+    fn from(repr: SourceRepr) -> Self {
+        match repr {
+            SourceRepr::Path(path) => AddedSource {
+                path,
+                options: AddOptions::default(),
+            },
+            SourceRepr::Added { path, options } => AddedSource { path, options },
+        }
+    }
+}
+
+/// An inclusive range of 1-based line numbers.
+pub type LineRange = (usize, usize);
+
+/// Where a file's contents come from. The variants are told apart by their field
+/// names, so state files from before remote files existed still load.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum Source {
+    /// A file on disk, by its canonical path
+    Local { absolute_path: PathBuf },
+    /// A document that is downloaded whenever it's printed
+    Remote { url: String },
+}
+
+impl FileEntry {
+    /// The path the file is shown as in printed prompts.
+    pub fn display_path(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.relative_path)
+    }
+
+    /// The file's path on disk, or `None` if it's a remote file.
+    pub fn local_path(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Local { absolute_path } => Some(absolute_path),
+            Source::Remote { .. } => None,
+        }
+    }
+
+    /// Where the file's contents come from, for messages: its path or URL.
+    pub fn location(&self) -> std::borrow::Cow<'_, str> {
+        match &self.source {
+            Source::Local { absolute_path } => absolute_path.to_string_lossy(),
+            Source::Remote { url } => url.as_str().into(),
+        }
+    }
+
+    /// Whether the file still exists. Remote files are assumed to.
+    pub fn exists(&self) -> bool {
+        self.local_path().is_none_or(Path::exists)
+    }
+
+    /// Reads the file's contents, downloading it if it's a remote file.
+    pub fn read(&self) -> Result<Vec<u8>, AppError> {
+        match &self.source {
+            Source::Local { absolute_path } => {
+                fs::read(absolute_path).map_err(|source| AppError::FileReadError {
+                    path: self.relative_path.clone(),
+                    source,
+                })
+            }
+            Source::Remote { url } => remote::fetch(url),
+        }
+    }
+
+    pub fn has_tag(&self, tag: Option<&str>) -> bool {
+        tag.is_none_or(|tag| self.tags.iter().any(|t| t == tag))
+    }
+}
+
+/// The collection of files, as stored in a state file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct State {
+    pub files: Vec<FileEntry>,
+    /// Everything passed to `add`, re-walked by `refresh`
+    #[serde(default)]
+    pub sources: Vec<AddedSource>,
+    /// Globs skipped by every `add` to this state, in addition to `--exclude`
+    #[serde(default)]
+    pub default_excludes: Vec<String>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl State {
+    /// Loads the state file at `path`. A missing or empty file is an empty state.
+    pub fn new(path: PathBuf) -> Result<Self, AppError> {
+        if path.exists() {
+            let contents = fs::read_to_string(&path)?;
+            if contents.trim().is_empty() {
+                return Ok(State {
+                    path,
+                    ..Default::default()
+                });
+            }
+            let mut state: State = serde_json::from_str(&contents)?;
+            state.path = path;
+            let mut orders: Vec<_> = state.files.iter().map(|file| file.order).collect();
+            orders.sort_unstable();
+            orders.dedup();
+            if orders.len() == state.files.len() {
+                state.files.sort_by_key(|file| file.order);
+            } else {
+                // State files from before `order` existed keep the order of the list.
+                state.renumber();
+            }
+            Ok(state)
+        } else {
+            Ok(State {
+                path,
+                ..Default::default()
+            })
+        }
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        self.save_to(&self.path)
+    }
+
+    /// Appends a file after all the others.
+    pub fn push_file(&mut self, mut entry: FileEntry) {
+        entry.order = self.files.last().map_or(0, |last| last.order + 1);
+        self.files.push(entry);
+    }
+
+    /// Makes the files' `order` match their position, after they were moved around.
+    pub fn renumber(&mut self) {
+        for (order, file) in (0..).zip(&mut self.files) {
+            file.order = order;
+        }
+    }
+
+    pub fn backup_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".bak");
+        path.into()
+    }
+
+    /// Copies the saved state next to itself before a destructive change, so
+    /// `restore` can undo it. This is best-effort and only warns on failure.
+    pub fn backup(&self) {
+        if self.path.exists() {
+            if let Err(e) = fs::copy(&self.path, self.backup_path()) {
+                eprintln!("Warning: Could not back up the state: {}", e);
+            }
+        }
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), AppError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use crate::load::format_thousands;
+use crate::State;
+use std::fmt;
+use std::path::Path;
+
+/// Character, word and line counts of a text that may be added in pieces.
+#[derive(Debug, Clone, Default)]
+pub struct TextStats {
+    chars: usize,
+    /// Words are runs of non-whitespace characters, as counted by `wc -w`
+    words: usize,
+    lines: usize,
+    in_word: bool,
+    /// Whether the text so far ends in the middle of a line
+    in_line: bool,
+}
+
+impl TextStats {
+    pub fn add(&mut self, s: &str) {
+        for c in s.chars() {
+            self.chars += 1;
+            if c.is_whitespace() {
+                self.in_word = false;
+            } else if !self.in_word {
+                self.in_word = true;
+                self.words += 1;
+            }
+            if c == '\n' {
+                self.lines += 1;
+                self.in_line = false;
+            } else {
+                self.in_line = true;
+            }
+        }
+    }
+
+    pub fn chars(&self) -> usize {
+        self.chars
+    }
+
+    pub fn words(&self) -> usize {
+        self.words
+    }
+
+    /// Lines, including a last line without a line ending
+    pub fn lines(&self) -> usize {
+        self.lines + usize::from(self.in_line)
+    }
+}
+
+impl fmt::Display for TextStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} characters, {} words and {} lines",
+            format_thousands(self.chars),
+            format_thousands(self.words),
+            format_thousands(self.lines())
+        )
+    }
+}
+
+/// Totals over the files in a state, as shown by `pb stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollectionStats {
+    pub files: usize,
+    pub total_bytes: u64,
+    pub total_lines: usize,
+    /// Files that couldn't be read, which are left out of the totals
+    pub unreadable: usize,
+    /// Bytes per lowercase extension with a leading `.`, or `(none)`, largest first
+    pub bytes_by_extension: Vec<(String, u64)>,
+}
+
+/// Reads every file in `state` and adds up their sizes and lines.
+pub fn collection_stats(state: &State) -> CollectionStats {
+    let mut stats = CollectionStats {
+        files: state.files.len(),
+        ..CollectionStats::default()
+    };
+    let mut bytes_by_extension: std::collections::HashMap<String, u64> = Default::default();
+    for file in &state.files {
+        let Ok(contents) = file.read() else {
+            stats.unreadable += 1;
+            continue;
+        };
+        let bytes = contents.len() as u64;
+        stats.total_bytes += bytes;
+        stats.total_lines += count_lines(&contents);
+        let extension = Path::new(&file.relative_path)
+            .extension()
+            .map(|extension| format!(".{}", extension.to_string_lossy().to_lowercase()))
+            .unwrap_or_else(|| "(none)".into());
+        *bytes_by_extension.entry(extension).or_default() += bytes;
+    }
+    stats.bytes_by_extension = bytes_by_extension.into_iter().collect();
+    stats
+        .bytes_by_extension
+        .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    stats
+}
+
+/// Counts lines the way editors do: a trailing line without `\n` still counts.
+pub fn count_lines(contents: &[u8]) -> usize {
+    let newlines = contents.iter().filter(|&&b| b == b'\n').count();
+    if contents.last().is_some_and(|&b| b != b'\n') {
+        newlines + 1
+    } else {
+        newlines
+    }
+}
@@ -0,0 +1,68 @@
+/// A piece of a prompt template, see `parse`.
+pub enum Part<'a> {
+    Text(&'a str),
+    /// `{{files}}`: all printed files
+    Files,
+    /// `{{file:PATH}}`: a single file, by its relative path
+    File {
+        path: &'a str,
+        /// Where the placeholder starts, for error messages
+        location: Location,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// Splits a template into text and `{{...}}` placeholders. Whitespace inside the
+/// braces is ignored, e.g. `{{ files }}` works too.
+pub fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        let location = location_of(template, template.len() - rest.len() + start);
+        let Some(length) = rest[start..].find("}}") else {
+            return Err(format!("unclosed {{{{ at {}", location));
+        };
+        let placeholder = rest[start + 2..start + length].trim();
+        parts.push(match placeholder.split_once(':') {
+            None if placeholder == "files" => Part::Files,
+            Some(("file", path)) if !path.trim().is_empty() => Part::File {
+                path: path.trim(),
+                location,
+            },
+            _ => {
+                return Err(format!(
+                    "unknown placeholder {{{{{}}}}} at {}",
+                    placeholder, location
+                ))
+            }
+        });
+        rest = &rest[start + length + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+fn location_of(s: &str, offset: usize) -> Location {
+    let before = &s[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Location {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
@@ -0,0 +1,86 @@
+/// Rough number of characters per token for typical source code and prose.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimates the number of LLM tokens in `s` using a simple chars/4 heuristic.
+pub fn estimate_tokens(s: &str) -> usize {
+    estimate_tokens_for_chars(s.chars().count())
+}
+
+/// Estimates the number of LLM tokens in a text of `chars` characters.
+pub fn estimate_tokens_for_chars(chars: usize) -> usize {
+    chars.div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Counts the tokens a model would see for a piece of text.
+pub trait Tokenizer {
+    fn count_tokens(&self, s: &str) -> usize;
+
+    /// Whether the count is exact, rather than an estimate.
+    fn is_exact(&self) -> bool;
+}
+
+/// The model-agnostic chars/4 estimate.
+pub struct Heuristic;
+
+impl Tokenizer for Heuristic {
+    fn count_tokens(&self, s: &str) -> usize {
+        estimate_tokens(s)
+    }
+
+    fn is_exact(&self) -> bool {
+        false
+    }
+}
+
+/// The BPE tokenizers used by OpenAI models.
+struct Tiktoken(&'static tiktoken_rs::CoreBPE);
+
+impl Tokenizer for Tiktoken {
+    fn count_tokens(&self, s: &str) -> usize {
+        // Special tokens in file contents are plain text to the model.
+        self.0.encode_ordinary(s).len()
+    }
+
+    fn is_exact(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the tokenizer for `model`, e.g. `gpt-4o`, or `None` if it's unknown.
+pub fn tokenizer_for_model(model: &str) -> Option<Box<dyn Tokenizer>> {
+    let bpe = tiktoken_rs::bpe_for_model(model).ok()?;
+    Some(Box::new(Tiktoken(bpe)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_a_token_per_four_characters() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens(&"x".repeat(400)), 100);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(estimate_tokens("äöüß"), 1);
+        assert_eq!(estimate_tokens_for_chars(9), 3);
+    }
+
+    #[test]
+    fn heuristic_is_an_estimate() {
+        assert_eq!(Heuristic.count_tokens("<file>"), 2);
+        assert!(!Heuristic.is_exact());
+    }
+
+    #[test]
+    fn unknown_models_have_no_tokenizer() {
+        assert!(tokenizer_for_model("not-a-model").is_none());
+        let tokenizer = tokenizer_for_model("gpt-4o").expect("gpt-4o is known");
+        assert!(tokenizer.is_exact());
+        assert!(tokenizer.count_tokens("hello world") > 0);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::natural;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+/// A directory in the hierarchy reconstructed from the stored relative paths.
+#[derive(Default)]
+struct Directory {
+    directories: BTreeMap<String, Directory>,
+    files: Vec<String>,
+}
+
+impl Directory {
+    fn insert(&mut self, path: &Path) {
+        let mut components: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::CurDir => None,
+                Component::RootDir => Some("/".into()),
+                other => Some(other.as_os_str().to_string_lossy().into_owned()),
+            })
+            .collect();
+        let Some(file) = components.pop() else {
+            return;
+        };
+        let mut directory = self;
+        for name in components {
+            directory = directory.directories.entry(name).or_default();
+        }
+        directory.files.push(file);
+    }
+
+    /// Merges chains of directories that only contain a single directory, so
+    /// `src/` → `app/` → files is shown as `src/app/` → files.
+    fn collapse(&mut self) {
+        let directories = std::mem::take(&mut self.directories);
+        for (mut name, mut directory) in directories {
+            while directory.files.is_empty() && directory.directories.len() == 1 {
+                let (child_name, child) = directory.directories.pop_first().unwrap();
+                name = join(&name, &child_name);
+                directory = child;
+            }
+            directory.collapse();
+            self.directories.insert(name, directory);
+        }
+        self.files.sort_by(|a, b| natural::compare(a, b));
+    }
+
+    /// Number of files in this directory and all directories below it.
+    fn file_count(&self) -> usize {
+        self.files.len()
+            + self
+                .directories
+                .values()
+                .map(Directory::file_count)
+                .sum::<usize>()
+    }
+
+    /// Renders the directory's contents, or a summary line instead of them once
+    /// `depth` levels were rendered.
+    fn render(&self, prefix: &str, depth: Option<usize>, output: &mut String) {
+        if depth == Some(0) {
+            output.push_str(&format!("{}└── (… {} more)\n", prefix, self.file_count()));
+            return;
+        }
+        let mut directories: Vec<_> = self.directories.iter().collect();
+        directories.sort_by(|(a, _), (b, _)| natural::compare(a, b));
+        let entries: Vec<(String, Option<&Directory>)> = directories
+            .into_iter()
+            .map(|(name, directory)| (format!("{}/", name.trim_end_matches('/')), Some(directory)))
+            .chain(self.files.iter().map(|name| (name.clone(), None)))
+            .collect();
+        for (i, (name, directory)) in entries.iter().enumerate() {
+            let last = i + 1 == entries.len();
+            output.push_str(prefix);
+            output.push_str(if last { "└── " } else { "├── " });
+            output.push_str(name);
+            output.push('\n');
+            if let Some(directory) = directory {
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                directory.render(&prefix, depth.map(|depth| depth - 1), output);
+            }
+        }
+    }
+}
+
+fn join(parent: &str, child: &str) -> String {
+    if parent.ends_with('/') {
+        format!("{}{}", parent, child)
+    } else {
+        format!("{}/{}", parent, child)
+    }
+}
+
+/// Renders the given file paths as a directory tree, with directories listed before
+/// the files next to them. Names are sorted naturally, so `v2/` comes before `v10/`.
+/// With a `max_depth`, the contents of directories at that depth are summarized as
+/// the number of files in them.
+pub fn render<'a>(paths: impl IntoIterator<Item = &'a str>, max_depth: Option<usize>) -> String {
+    let mut root = Directory::default();
+    for path in paths {
+        root.insert(Path::new(path));
+    }
+    root.collapse();
+    let mut output = String::from(".\n");
+    root.render("", max_depth, &mut output);
+    output
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+
+#[test]
+fn adding_two_directories_gives_the_union_of_both_trees() {
+    let fixture = Fixture::new();
+    fixture.write("src/lib.rs", "pub mod a;");
+    fixture.write("src/a/mod.rs", "pub fn a() {}");
+    fixture.write("tests/it.rs", "#[test] fn it() {}");
+    fixture.write("docs/guide.md", "# Guide");
+
+    assert_eq!(
+        fixture.add(&["src", "tests"], AddOptions::default()),
+        ["src/a/mod.rs", "src/lib.rs", "tests/it.rs"]
+    );
+}
+
+#[test]
+fn files_directories_and_globs_can_be_mixed() {
+    let fixture = Fixture::new();
+    fixture.write("README.md", "# Readme");
+    fixture.write("src/lib.rs", "pub fn f() {}");
+    fixture.write("tests/it.rs", "#[test] fn it() {}");
+    fixture.write("tests/data.json", "{}");
+
+    assert_eq!(
+        fixture.add(&["README.md", "src", "tests/*.rs"], AddOptions::default()),
+        ["README.md", "src/lib.rs", "tests/it.rs"]
+    );
+}
+
+#[test]
+fn excludes_apply_to_every_argument() {
+    let fixture = Fixture::new();
+    fixture.write("src/lib.rs", "pub fn f() {}");
+    fixture.write("src/lib.snap", "src snapshot");
+    fixture.write("tests/it.rs", "#[test] fn it() {}");
+    fixture.write("tests/it.snap", "tests snapshot");
+
+    let options = AddOptions {
+        exclude: vec!["*.snap".into()],
+        ..AddOptions::default()
+    };
+    assert_eq!(
+        fixture.add(&["src", "tests"], options),
+        ["src/lib.rs", "tests/it.rs"]
+    );
+}
+
+#[test]
+fn overlapping_arguments_add_each_file_once() {
+    let fixture = Fixture::new();
+    fixture.write("src/lib.rs", "pub fn f() {}");
+    fixture.write("src/bin/main.rs", "fn main() {}");
+
+    assert_eq!(
+        fixture.add(&["src", "src/bin", "src/lib.rs"], AddOptions::default()),
+        ["src/bin/main.rs", "src/lib.rs"]
+    );
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+use prompt_builder_rs::load::{self, LoadOptions};
+use prompt_builder_rs::{Format, Prompt, RenderOptions, State};
+
+const BOM: &str = "\u{FEFF}";
+
+#[test]
+fn loading_a_file_strips_its_byte_order_mark() {
+    let fixture = Fixture::new();
+    fixture.write("windows.cs", format!("{}class Program {{}}\r\n", BOM));
+    let mut state = State::default();
+    fixture.add_to(&mut state, &["windows.cs"], AddOptions::default());
+
+    let loaded = load::load_file(&state.files[0], &LoadOptions::default(), None).unwrap();
+    assert_eq!(loaded.prompt.contents, "class Program {}\r\n");
+}
+
+#[test]
+fn printed_output_does_not_contain_byte_order_marks() {
+    let fixture = Fixture::new();
+    fixture.write("a.txt", format!("{}first", BOM));
+    fixture.write("b.txt", format!("{}second", BOM));
+    let prompt = Prompt::from_paths([fixture.path("a.txt"), fixture.path("b.txt")]).unwrap();
+
+    for format in [Format::Xml, Format::Markdown] {
+        let output = prompt.render(&RenderOptions {
+            format,
+            ..RenderOptions::default()
+        });
+        assert!(!output.contains(BOM), "{:?} output has a BOM", format);
+        assert!(output.contains("first") && output.contains("second"));
+    }
+}
+
+#[test]
+fn only_a_leading_byte_order_mark_is_stripped() {
+    let fixture = Fixture::new();
+    fixture.write("inner.txt", format!("a{}b", BOM));
+    let prompt = Prompt::from_paths([fixture.path("inner.txt")]).unwrap();
+
+    assert_eq!(prompt.files[0].contents, format!("a{}b", BOM));
+}
@@ -0,0 +1,73 @@
+//! Temporary project directories for the integration tests.
+#![allow(dead_code)]
+
+use prompt_builder_rs::add::{self, AddOptions};
+use prompt_builder_rs::State;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A project directory under the system temp dir, removed when it's dropped. It
+/// has a `.git` directory, so git ignore files apply inside it.
+pub struct Fixture {
+    pub root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("pb-test-{}-{}", std::process::id(), id));
+        fs::create_dir_all(root.join(".git")).unwrap();
+        Fixture {
+            root: fs::canonicalize(root).unwrap(),
+        }
+    }
+
+    /// Writes `contents` to `path` inside the fixture, creating its parent directories.
+    pub fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Adds `patterns`, which are inside the fixture, to an empty state and returns
+    /// the stored paths relative to the fixture.
+    pub fn add(&self, patterns: &[&str], options: AddOptions) -> Vec<String> {
+        let mut state = State::default();
+        self.add_to(&mut state, patterns, options);
+        relative_paths(&state)
+    }
+
+    pub fn add_to(&self, state: &mut State, patterns: &[&str], options: AddOptions) {
+        let patterns: Vec<String> = patterns
+            .iter()
+            .map(|pattern| self.path(pattern).to_string_lossy().into_owned())
+            .collect();
+        let options = AddOptions {
+            relative_to: Some(self.root.clone()),
+            ..options
+        };
+        add::add_files(state, &patterns, &options).unwrap();
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+pub fn relative_paths(state: &State) -> Vec<String> {
+    state
+        .files
+        .iter()
+        .map(|file| file.relative_path.clone())
+        .collect()
+}
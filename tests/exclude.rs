@@ -0,0 +1,37 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+
+#[test]
+fn exclude_skips_matching_children_of_a_directory() {
+    let fixture = Fixture::new();
+    fixture.write("web/app.js", "app();");
+    fixture.write("web/app.min.js", "app();app();");
+    fixture.write("web/generated/schema.js", "schema();");
+    fixture.write("web/index.html", "<html></html>");
+
+    let options = AddOptions {
+        exclude: vec!["*.min.js".into(), "generated/".into()],
+        ..AddOptions::default()
+    };
+    assert_eq!(
+        fixture.add(&["web"], options),
+        ["web/app.js", "web/index.html"]
+    );
+}
+
+#[test]
+fn exclude_does_not_skip_files_named_explicitly() {
+    let fixture = Fixture::new();
+    fixture.write("web/app.min.js", "app();");
+
+    let options = AddOptions {
+        exclude: vec!["*.min.js".into()],
+        ..AddOptions::default()
+    };
+    assert_eq!(
+        fixture.add(&["web/app.min.js"], options),
+        ["web/app.min.js"]
+    );
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+
+#[test]
+fn gitignored_files_are_skipped_by_default() {
+    let fixture = Fixture::new();
+    fixture.write(".gitignore", "secret.env\n");
+    fixture.write("main.rs", "fn main() {}");
+    fixture.write("secret.env", "KEY=1");
+
+    assert_eq!(fixture.add(&["."], AddOptions::default()), ["main.rs"]);
+}
+
+#[test]
+fn no_ignore_adds_gitignored_files() {
+    let fixture = Fixture::new();
+    fixture.write(".gitignore", "secret.env\n");
+    fixture.write("main.rs", "fn main() {}");
+    fixture.write("secret.env", "KEY=1");
+
+    let options = AddOptions {
+        no_ignore: true,
+        ..AddOptions::default()
+    };
+    assert_eq!(fixture.add(&["."], options), ["main.rs", "secret.env"]);
+}
+
+#[test]
+fn promptignore_hides_tracked_files() {
+    let fixture = Fixture::new();
+    fixture.write(".promptignore", "*.snap\n");
+    fixture.write("src/lib.rs", "pub fn f() {}");
+    fixture.write("src/snapshots/f.snap", "snapshot");
+    fixture.write("tests/fixtures/.promptignore", "big.json\n");
+    fixture.write("tests/fixtures/big.json", "{}");
+    fixture.write("tests/fixtures/small.json", "[]");
+
+    assert_eq!(
+        fixture.add(&["."], AddOptions::default()),
+        ["src/lib.rs", "tests/fixtures/small.json"]
+    );
+}
+
+#[test]
+fn promptignore_can_re_include_gitignored_files() {
+    let fixture = Fixture::new();
+    fixture.write(".gitignore", "*.env\n");
+    fixture.write(".promptignore", "!example.env\n");
+    fixture.write("example.env", "KEY=");
+    fixture.write("secret.env", "KEY=1");
+
+    assert_eq!(fixture.add(&["."], AddOptions::default()), ["example.env"]);
+}
+
+#[test]
+fn no_ignore_disables_promptignore() {
+    let fixture = Fixture::new();
+    fixture.write(".promptignore", "*.snap\n");
+    fixture.write("f.snap", "snapshot");
+
+    let options = AddOptions {
+        no_ignore: true,
+        ..AddOptions::default()
+    };
+    assert_eq!(fixture.add(&["."], options), ["f.snap"]);
+}
+
+fn monorepo_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write(".gitignore", "*.log\n");
+    fixture.write("packages/app/.gitignore", "dist/\n");
+    fixture.write("packages/app/main.js", "main();");
+    fixture.write("packages/app/debug.log", "debug");
+    fixture.write("packages/app/dist/bundle.js", "bundle();");
+    fixture
+}
+
+#[test]
+fn parent_gitignores_apply_to_a_subtree_by_default() {
+    let fixture = monorepo_fixture();
+
+    assert_eq!(
+        fixture.add(&["packages/app"], AddOptions::default()),
+        ["packages/app/main.js"]
+    );
+}
+
+#[test]
+fn no_parent_ignore_only_applies_gitignores_within_the_subtree() {
+    let fixture = monorepo_fixture();
+
+    let options = AddOptions {
+        no_parent_ignore: true,
+        ..AddOptions::default()
+    };
+    assert_eq!(
+        fixture.add(&["packages/app"], options),
+        ["packages/app/debug.log", "packages/app/main.js"]
+    );
+}
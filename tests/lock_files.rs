@@ -0,0 +1,26 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+
+#[test]
+fn lock_files_are_skipped_by_default() {
+    let fixture = Fixture::new();
+    fixture.write("Cargo.toml", "[package]");
+    fixture.write("Cargo.lock", "version = 3");
+
+    assert_eq!(fixture.add(&["."], AddOptions::default()), ["Cargo.toml"]);
+}
+
+#[test]
+fn include_lock_adds_lock_files() {
+    let fixture = Fixture::new();
+    fixture.write("Cargo.toml", "[package]");
+    fixture.write("Cargo.lock", "version = 3");
+
+    let options = AddOptions {
+        include_lock: true,
+        ..AddOptions::default()
+    };
+    assert_eq!(fixture.add(&["."], options), ["Cargo.lock", "Cargo.toml"]);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+
+fn nested_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("proj/top.rs", "// top");
+    fixture.write("proj/sub/middle.rs", "// middle");
+    fixture.write("proj/sub/deep/bottom.rs", "// bottom");
+    fixture
+}
+
+fn with_max_depth(max_depth: Option<usize>) -> AddOptions {
+    AddOptions {
+        max_depth,
+        ..AddOptions::default()
+    }
+}
+
+#[test]
+fn depth_0_adds_nothing_from_a_directory() {
+    let fixture = nested_fixture();
+
+    assert!(fixture.add(&["proj"], with_max_depth(Some(0))).is_empty());
+}
+
+#[test]
+fn depth_0_still_adds_a_file_named_explicitly() {
+    let fixture = nested_fixture();
+
+    assert_eq!(
+        fixture.add(&["proj/top.rs"], with_max_depth(Some(0))),
+        ["proj/top.rs"]
+    );
+}
+
+#[test]
+fn depth_1_adds_only_the_directorys_own_files() {
+    let fixture = nested_fixture();
+
+    assert_eq!(
+        fixture.add(&["proj"], with_max_depth(Some(1))),
+        ["proj/top.rs"]
+    );
+}
+
+#[test]
+fn depth_2_descends_one_level() {
+    let fixture = nested_fixture();
+
+    assert_eq!(
+        fixture.add(&["proj"], with_max_depth(Some(2))),
+        ["proj/sub/middle.rs", "proj/top.rs"]
+    );
+}
+
+#[test]
+fn depth_is_unlimited_by_default() {
+    let fixture = nested_fixture();
+
+    assert_eq!(
+        fixture.add(&["proj"], with_max_depth(None)),
+        [
+            "proj/sub/deep/bottom.rs",
+            "proj/sub/middle.rs",
+            "proj/top.rs"
+        ]
+    );
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::{relative_paths, Fixture};
+use prompt_builder_rs::add::{self, AddOptions};
+use prompt_builder_rs::State;
+
+/// Adds `pattern` to an empty state from `cwd`.
+fn add_from(cwd: &std::path::Path, pattern: &str, options: &AddOptions) -> Vec<String> {
+    std::env::set_current_dir(cwd).unwrap();
+    let mut state = State::default();
+    add::add_files(&mut state, &[pattern.to_string()], options).unwrap();
+    relative_paths(&state)
+}
+
+// The only test in this file, since it changes the current directory of the
+// whole test process.
+#[test]
+fn adding_a_directory_from_different_cwds_gives_the_same_order() {
+    let fixture = Fixture::new();
+    for path in [
+        "zeta.rs", "b/y.rs", "a/x.rs", "Alpha.rs", "a/b/c.rs", "b.rs", "a.rs",
+    ] {
+        fixture.write(&format!("proj/{}", path), path);
+    }
+    let options = AddOptions {
+        relative_to: Some(fixture.path("proj")),
+        ..AddOptions::default()
+    };
+
+    let original_cwd = std::env::current_dir().unwrap();
+    let from_inside = add_from(&fixture.path("proj"), ".", &options);
+    let from_parent = add_from(&fixture.root, "proj", &options);
+    let from_subdir = add_from(&fixture.path("proj/a"), "..", &options);
+    std::env::set_current_dir(original_cwd).unwrap();
+
+    let expected = [
+        "Alpha.rs", "a/b/c.rs", "a/x.rs", "a.rs", "b/y.rs", "b.rs", "zeta.rs",
+    ];
+    assert_eq!(from_inside, expected);
+    assert_eq!(from_parent, expected);
+    assert_eq!(from_subdir, expected);
+}
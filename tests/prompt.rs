@@ -0,0 +1,60 @@
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::{Format, Prompt, RenderOptions};
+
+/// A project with files that `pb add` skips by default next to ones it keeps.
+fn project() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write(".gitignore", "target/\n");
+    fixture.write("README.md", "# Demo\n");
+    fixture.write("src/main.rs", "fn main() {}\n");
+    fixture.write("src/lib.rs", "pub fn f() -> bool { 1 < 2 }\n");
+    fixture.write("src/.hidden.rs", "hidden\n");
+    fixture.write("target/out.rs", "ignored\n");
+    fixture.write("Cargo.lock", "locked\n");
+    fixture.write("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0");
+    fixture
+}
+
+#[test]
+fn from_paths_reads_the_files_pb_add_would() {
+    let fixture = project();
+    // The fixture is outside the project root of the test run, so paths are absolute
+    let root = fixture.root.to_string_lossy().into_owned();
+
+    let prompt = Prompt::from_paths([fixture.path("src"), fixture.root.clone()]).unwrap();
+    let paths: Vec<_> = prompt.files.iter().map(|file| file.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            format!("{}/src/lib.rs", root),
+            format!("{}/src/main.rs", root),
+            format!("{}/README.md", root),
+        ]
+    );
+}
+
+#[test]
+fn from_paths_then_render_produces_the_printed_prompt() {
+    let fixture = project();
+    let root = fixture.root.to_string_lossy().into_owned();
+    let prompt = Prompt::from_paths([fixture.path("src")]).unwrap();
+
+    assert_eq!(
+        prompt.render(&RenderOptions::default()),
+        format!(
+            "<files>\n\
+             <file path=\"{root}/src/lib.rs\">\npub fn f() -&gt; bool {{ 1 &lt; 2 }}\n\n</file>\n\
+             <file path=\"{root}/src/main.rs\">\nfn main() {{}}\n\n</file>\n\
+             </files>\n",
+            root = root
+        )
+    );
+    let markdown = prompt.render(&RenderOptions {
+        format: Format::Markdown,
+        ..RenderOptions::default()
+    });
+    assert!(markdown.contains("pub fn f() -> bool { 1 < 2 }"));
+    assert!(!markdown.contains("&lt;"));
+}
@@ -0,0 +1,55 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::Fixture;
+use prompt_builder_rs::add::AddOptions;
+use std::os::unix::fs::symlink;
+
+fn fixture_with_symlinked_dir() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write("real/lib.rs", "pub fn f() {}");
+    fixture.write("real/nested/mod.rs", "mod nested;");
+    fixture.write("src/main.rs", "fn main() {}");
+    symlink(fixture.path("real"), fixture.path("src/linked")).unwrap();
+    fixture
+}
+
+#[test]
+fn symlinked_directories_are_skipped_by_default() {
+    let fixture = fixture_with_symlinked_dir();
+
+    assert_eq!(
+        fixture.add(&["src"], AddOptions::default()),
+        ["src/main.rs"]
+    );
+}
+
+#[test]
+fn follow_symlinks_adds_files_of_a_symlinked_directory() {
+    let fixture = fixture_with_symlinked_dir();
+
+    let options = AddOptions {
+        follow_symlinks: true,
+        ..AddOptions::default()
+    };
+    // Stored under the real path, since paths are canonicalized.
+    assert_eq!(
+        fixture.add(&["src"], options),
+        ["real/lib.rs", "real/nested/mod.rs", "src/main.rs"]
+    );
+}
+
+#[test]
+fn files_reached_through_a_symlink_and_its_target_are_added_once() {
+    let fixture = fixture_with_symlinked_dir();
+
+    let options = AddOptions {
+        follow_symlinks: true,
+        ..AddOptions::default()
+    };
+    assert_eq!(
+        fixture.add(&["real", "src"], options),
+        ["real/lib.rs", "real/nested/mod.rs", "src/main.rs"]
+    );
+}